@@ -0,0 +1,946 @@
+use crate::cose;
+use crate::error::CatTesterError;
+use crate::inspect::{self, CAT_CLAIMS, MATCH_TYPES, RENEWAL_PARAMS, URI_COMPONENTS};
+use common_access_token::{CborValue, cat_keys, catu, match_types};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// CAT claims the verify command and the mock CDN check
+const CHECKED_CLAIMS: [i32; 7] = [
+    cat_keys::CATU,
+    cat_keys::CATM,
+    cat_keys::CATNIP,
+    cat_keys::CATH,
+    cat_keys::CATGEOISO3166,
+    cat_keys::GEOHASH,
+    cat_keys::CATV,
+];
+
+/// Byte string in a claims file, written as `{ hex = "0a000001" }`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HexBytes {
+    pub hex: String,
+}
+
+/// Value of a CAT claim as written in a claims file. Map keys are numbers
+/// or the names used by inspect, like `path`, `prefix` or `exp-add`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ClaimValue {
+    Integer(i64),
+    Text(String),
+    Bytes(HexBytes),
+    Array(Vec<ClaimValue>),
+    Map(BTreeMap<String, ClaimValue>),
+}
+
+/// One CATU match, given as `component:match:value` like `path:prefix:/live/`.
+/// A sha256 match hashes the value
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct UriMatch {
+    pub component: i32,
+    pub match_type: i32,
+    pub value: String,
+}
+
+impl FromStr for UriMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(component), Some(match_type), Some(value)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("expected component:match:value, got {}", s));
+        };
+        let component = inspect::key_of(&URI_COMPONENTS, component)
+            .ok_or_else(|| format!("unknown uri component {}", component))?;
+        let match_type = parse_match_type(match_type)?;
+        Ok(Self {
+            component,
+            match_type,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for UriMatch {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// exact, prefix, suffix, contains, regex or sha256
+fn parse_match_type(name: &str) -> Result<i32, String> {
+    match name {
+        "sha256" => Some(match_types::SHA256),
+        name => inspect::key_of(&MATCH_TYPES, name).filter(|&t| t != match_types::SHA512_256),
+    }
+    .ok_or_else(|| format!("unsupported match type {}", name))
+}
+
+// A sha256 match hashes the value
+fn to_match(match_type: i32, value: &str) -> BTreeMap<i32, CborValue> {
+    match match_type {
+        match_types::PREFIX => catu::prefix_match(value),
+        match_types::SUFFIX => catu::suffix_match(value),
+        match_types::CONTAINS => catu::contains_match(value),
+        match_types::REGEX => catu::regex_match(value, vec![]),
+        match_types::SHA256 => catu::sha256_match(&Sha256::digest(value.as_bytes())),
+        _ => catu::exact_match(value),
+    }
+}
+
+/// One CATH match, given as `header:match:value` like `User-Agent:prefix:Mozilla`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct HeaderMatch {
+    pub name: String,
+    pub match_type: i32,
+    pub value: String,
+}
+
+impl FromStr for HeaderMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(name), Some(match_type), Some(value)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("expected header:match:value, got {}", s));
+        };
+        if name.trim().is_empty() {
+            return Err(format!("no header name in {}", s));
+        }
+        Ok(Self {
+            name: name.trim().to_string(),
+            match_type: parse_match_type(match_type)?,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for HeaderMatch {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The CATH claim for a list of matches, the encoded map from header name to
+/// match. The token library only has integer map keys, see cose::seal
+pub fn cath_claim(matches: &[HeaderMatch]) -> Vec<u8> {
+    let mut headers: Vec<(&str, BTreeMap<i32, CborValue>)> = Vec::new();
+    for m in matches {
+        let header = match headers
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&m.name))
+        {
+            Some((_, header)) => header,
+            None => {
+                headers.push((&m.name, BTreeMap::new()));
+                &mut headers.last_mut().expect("header was just added").1
+            }
+        };
+        header.extend(to_match(m.match_type, &m.value));
+    }
+    let entries: Vec<(&str, Vec<u8>)> = headers
+        .into_iter()
+        .map(|(name, matches)| (name, cose::encode(&CborValue::Map(matches))))
+        .collect();
+    cose::encode_text_map(&entries)
+}
+
+/// A header in a CATIF response, given as `name: value` like
+/// `Location: https://auth.example.com/`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct ResponseHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for ResponseHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => Ok(Self {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            }),
+            _ => Err(format!("expected name: value, got {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for ResponseHeader {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The response a CDN sends instead of rejecting a token that does not
+/// allow the request, the CATIF claim
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Conditional {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<ResponseHeader>,
+}
+
+impl Conditional {
+    /// The encoded map from the key of each claim in `claims` to the status
+    /// and a map of the headers, the CTA-5007 CATIF claim
+    fn to_cbor(&self, claims: &[i32]) -> Vec<u8> {
+        let headers: Vec<(&str, Vec<u8>)> = self
+            .headers
+            .iter()
+            .map(|h| {
+                (
+                    h.name.as_str(),
+                    cose::encode(&CborValue::Text(h.value.clone())),
+                )
+            })
+            .collect();
+        let response = cose::encode_array(&[
+            cose::encode(&CborValue::Integer(i64::from(self.status))),
+            cose::encode_text_map(&headers),
+        ]);
+        let entries: Vec<(i64, Vec<u8>)> = claims
+            .iter()
+            .map(|claim| (i64::from(*claim), response.clone()))
+            .collect();
+        cose::encode_map(&entries)
+    }
+}
+
+/// The CATU claim for a list of matches, matches on the same component
+/// must all pass
+pub fn catu_claim(matches: &[UriMatch]) -> BTreeMap<i32, CborValue> {
+    let mut components: BTreeMap<i32, BTreeMap<i32, CborValue>> = BTreeMap::new();
+    for m in matches {
+        components
+            .entry(m.component)
+            .or_default()
+            .extend(to_match(m.match_type, &m.value));
+    }
+    catu::create(components)
+}
+
+/// A network for the CATNIP claim, given as an address, a prefix like
+/// 10.0.0.0/8 or 2001:db8::/32, or an autonomous system number like AS64500
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Network {
+    Prefix(IpAddr, u8),
+    Asn(u32),
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(asn) = s.strip_prefix("AS").or_else(|| s.strip_prefix("as")) {
+            return asn
+                .parse()
+                .map(Network::Asn)
+                .map_err(|_| format!("invalid asn {}", s));
+        }
+        let (address, prefix) = s.split_once('/').unwrap_or((s, ""));
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("invalid network {}", s))?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = if prefix.is_empty() {
+            max
+        } else {
+            prefix
+                .parse()
+                .ok()
+                .filter(|&p| p <= max)
+                .ok_or_else(|| format!("invalid prefix length in {}", s))?
+        };
+        Ok(Network::Prefix(address, prefix))
+    }
+}
+
+impl TryFrom<String> for Network {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Network {
+    // A prefix is [prefix length, address bytes] with the tag of the address
+    // family, RFC 9164. The bits after the prefix are cleared and trailing
+    // zero bytes dropped. An ASN is an integer
+    fn to_cbor(&self) -> Vec<u8> {
+        match self {
+            Network::Prefix(address, prefix) => {
+                let (tag, octets) = match address {
+                    IpAddr::V4(address) => (cose::IPV4_TAG, address.octets().to_vec()),
+                    IpAddr::V6(address) => (cose::IPV6_TAG, address.octets().to_vec()),
+                };
+                let mut bytes = masked(&octets, *prefix);
+                while bytes.last() == Some(&0) {
+                    bytes.pop();
+                }
+                let prefix = CborValue::Array(vec![
+                    CborValue::Integer(i64::from(*prefix)),
+                    CborValue::Bytes(bytes),
+                ]);
+                cose::encode_tagged(tag, &cose::encode(&prefix))
+            }
+            Network::Asn(asn) => cose::encode(&CborValue::Integer(i64::from(*asn))),
+        }
+    }
+}
+
+// The address with the bits after the prefix set to zero
+fn masked(octets: &[u8], prefix: u8) -> Vec<u8> {
+    octets
+        .iter()
+        .enumerate()
+        .map(|(i, octet)| {
+            let bits = usize::from(prefix).saturating_sub(i * 8).min(8);
+            octet & !(0xffu8.checked_shr(bits as u32).unwrap_or(0))
+        })
+        .collect()
+}
+
+/// How the CDN handles a token that is used again, the CATREPLAY claim
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Replay {
+    Permitted,
+    Prohibited,
+    /// The token can be used again but the CDN may notice it
+    ReuseDetection,
+}
+
+impl Replay {
+    pub fn value(self) -> i64 {
+        match self {
+            Replay::Permitted => 0,
+            Replay::Prohibited => 1,
+            Replay::ReuseDetection => 2,
+        }
+    }
+}
+
+/// How the CDN hands out a renewed token, the type in the CATR claim
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RenewalType {
+    /// A cookie for a token sent as cookie or query, otherwise a header
+    Automatic,
+    Cookie,
+    Header,
+    /// A redirect to the same url with the renewed token in the query
+    Redirect,
+}
+
+/// Settings for the CATR claim made for the token type. Settings that are
+/// not given keep the values the tool has always used
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Renewal {
+    /// Default header for the header token type, otherwise cookie
+    #[serde(rename = "type")]
+    pub kind: Option<RenewalType>,
+    /// Seconds a renewed token is valid, default ttl
+    pub exp_add: Option<u64>,
+    /// Seconds from iat to when the token should be renewed, default half a ttl
+    pub deadline: Option<u64>,
+    /// Cookie or header name, default CTA-Common-Access-Token
+    pub name: Option<String>,
+    /// Cookie or header parameters, the cookie default is Secure, HttpOnly,
+    /// the domain, path=/ and SameSite=None
+    pub params: Option<Vec<String>>,
+    /// Status of a redirect renewal, the CDN picks one if not given
+    pub code: Option<u16>,
+}
+
+impl Renewal {
+    /// Settings in `other` replace the ones in self
+    pub fn merge(self, other: Renewal) -> Self {
+        Self {
+            kind: other.kind.or(self.kind),
+            exp_add: other.exp_add.or(self.exp_add),
+            deadline: other.deadline.or(self.deadline),
+            name: other.name.or(self.name),
+            params: other.params.or(self.params),
+            code: other.code.or(self.code),
+        }
+    }
+}
+
+/// The claims of a created token. Claims that are not given keep the
+/// values the tool has always used
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClaimSet {
+    /// Replaces --issuer
+    pub iss: Option<String>,
+    pub sub: Option<String>,
+    pub aud: Option<String>,
+    /// Seconds from iat to exp, default two ttl
+    pub exp: Option<u64>,
+    /// Seconds from iat to nbf, negative for a time before iat
+    pub nbf: Option<i64>,
+    /// cti as hex
+    pub cti: Option<String>,
+    /// Key id in the unprotected header, or the protected header if it is in crit
+    pub kid: Option<String>,
+    /// Labels of the COSE header parameters the CDN must understand, the
+    /// protected crit header
+    #[serde(default)]
+    pub crit: Vec<i64>,
+    /// Matches for the CATU claim, replaces a catu in `claims`
+    #[serde(default)]
+    pub catu: Vec<UriMatch>,
+    /// Allowed methods for the CATM claim, replaces a catm in `claims`
+    #[serde(default)]
+    pub catm: Vec<String>,
+    /// Networks for the CATNIP claim, replaces a catnip in `claims`
+    #[serde(default)]
+    pub catnip: Vec<Network>,
+    /// ISO 3166 country or subdivision codes for the CATGEOISO3166 claim,
+    /// replaces a catgeoiso3166 in `claims`
+    #[serde(default)]
+    pub catgeoiso3166: Vec<String>,
+    /// Geohash cells for the geohash claim, replaces a geohash in `claims`
+    #[serde(default, alias = "catgeohash")]
+    pub geohash: Vec<String>,
+    /// CATREPLAY claim, replaces a catreplay in `claims`
+    pub catreplay: Option<Replay>,
+    /// Matches for the CATH claim, replaces a cath in `claims`
+    #[serde(default)]
+    pub cath: Vec<HeaderMatch>,
+    /// CATV claim, replaces a catv in `claims`
+    pub catv: Option<i64>,
+    /// CATIF claim, replaces a catif in `claims`
+    pub catif: Option<Conditional>,
+    /// CATIFDATA claim, replaces a catifdata in `claims`
+    pub catifdata: Option<String>,
+    /// Settings for the CATR claim, not used if `claims` has a catr
+    #[serde(default)]
+    pub renewal: Renewal,
+    /// CAT claims by name, like `catu`, or by number. A catr claim replaces
+    /// the one made for the token type
+    #[serde(default)]
+    pub claims: BTreeMap<String, ClaimValue>,
+}
+
+impl ClaimSet {
+    /// Read a claims file, json if the file name ends with .json otherwise toml
+    pub fn from_file(path: &Path) -> Result<Self, CatTesterError> {
+        let invalid = |e: String| CatTesterError::Config(format!("{}: {}", path.display(), e));
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let claims: Self = if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+        {
+            serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|e| invalid(e.to_string()))?
+        };
+        // report mistakes when the file is read and not when the token is made
+        claims.cti().map_err(|e| invalid(e.to_string()))?;
+        claims.custom().map_err(|e| invalid(e.to_string()))?;
+        Ok(claims)
+    }
+
+    pub fn cti(&self) -> Result<Option<Vec<u8>>, CatTesterError> {
+        self.cti
+            .as_deref()
+            .map(|cti| decode_hex(cti, "cti"))
+            .transpose()
+    }
+
+    /// Add matches given on the command line, they replace a match in the
+    /// file for the same component and match type
+    pub fn with_catu(mut self, catu: Vec<UriMatch>) -> Self {
+        self.catu.extend(catu);
+        self
+    }
+
+    /// Allowed methods given on the command line replace the ones in the file
+    pub fn with_catm(self, catm: Vec<String>) -> Self {
+        if catm.is_empty() {
+            return self;
+        }
+        Self { catm, ..self }
+    }
+
+    /// Networks given on the command line replace the ones in the file
+    pub fn with_catnip(self, catnip: Vec<Network>) -> Self {
+        if catnip.is_empty() {
+            return self;
+        }
+        Self { catnip, ..self }
+    }
+
+    /// Countries given on the command line replace the ones in the file
+    pub fn with_catgeoiso3166(self, catgeoiso3166: Vec<String>) -> Self {
+        if catgeoiso3166.is_empty() {
+            return self;
+        }
+        Self {
+            catgeoiso3166,
+            ..self
+        }
+    }
+
+    /// Geohash cells given on the command line replace the ones in the file
+    pub fn with_geohash(self, geohash: Vec<String>) -> Self {
+        if geohash.is_empty() {
+            return self;
+        }
+        Self { geohash, ..self }
+    }
+
+    /// Add header matches given on the command line, they replace a match in
+    /// the file for the same header and match type
+    pub fn with_cath(mut self, cath: Vec<HeaderMatch>) -> Self {
+        self.cath.extend(cath);
+        self
+    }
+
+    /// A replay setting on the command line replaces the one in the file
+    pub fn with_catreplay(self, catreplay: Option<Replay>) -> Self {
+        Self {
+            catreplay: catreplay.or(self.catreplay),
+            ..self
+        }
+    }
+
+    /// Critical header parameters on the command line replace the ones in the file
+    pub fn with_crit(self, crit: Vec<i64>) -> Self {
+        if crit.is_empty() {
+            return self;
+        }
+        Self { crit, ..self }
+    }
+
+    /// A version on the command line replaces the one in the file
+    pub fn with_catv(self, catv: Option<i64>) -> Self {
+        Self {
+            catv: catv.or(self.catv),
+            ..self
+        }
+    }
+
+    /// A CATIF status on the command line replaces the response in the file
+    pub fn with_catif(self, status: Option<u16>, headers: Vec<ResponseHeader>) -> Self {
+        match status {
+            Some(status) => Self {
+                catif: Some(Conditional { status, headers }),
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    /// CATIFDATA on the command line replaces the one in the file
+    pub fn with_catifdata(self, catifdata: Option<String>) -> Self {
+        Self {
+            catifdata: catifdata.or(self.catifdata),
+            ..self
+        }
+    }
+
+    /// Renewal settings on the command line replace the ones in the file
+    pub fn with_renewal(self, renewal: Renewal) -> Self {
+        Self {
+            renewal: self.renewal.merge(renewal),
+            ..self
+        }
+    }
+
+    /// The CAT claims as CBOR by claim number
+    pub fn custom(&self) -> Result<BTreeMap<i32, CborValue>, CatTesterError> {
+        let mut custom = self
+            .claims
+            .iter()
+            .map(|(name, value)| {
+                let key = name
+                    .parse()
+                    .ok()
+                    .or_else(|| inspect::key_of(&CAT_CLAIMS, name))
+                    .ok_or_else(|| CatTesterError::Config(format!("unknown claim {}", name)))?;
+                Ok((key, to_cbor(value)?))
+            })
+            .collect::<Result<BTreeMap<_, _>, CatTesterError>>()?;
+        if !self.catu.is_empty() {
+            custom.insert(cat_keys::CATU, CborValue::Map(catu_claim(&self.catu)));
+        }
+        if !self.catm.is_empty() {
+            let methods: Vec<String> = self.catm.iter().map(|m| m.to_uppercase()).collect();
+            custom.insert(cat_keys::CATM, CborValue::Text(methods.join(",")));
+        }
+        if !self.catnip.is_empty() {
+            let networks: Vec<Vec<u8>> = self.catnip.iter().map(Network::to_cbor).collect();
+            custom.insert(
+                cat_keys::CATNIP,
+                CborValue::Bytes(cose::encode_array(&networks)),
+            );
+        }
+        if !self.catgeoiso3166.is_empty() {
+            let codes = self
+                .catgeoiso3166
+                .iter()
+                .map(|code| CborValue::Text(code.to_uppercase()))
+                .collect();
+            custom.insert(cat_keys::CATGEOISO3166, CborValue::Array(codes));
+        }
+        if !self.geohash.is_empty() {
+            let cells = self
+                .geohash
+                .iter()
+                .map(|cell| CborValue::Text(cell.to_lowercase()))
+                .collect();
+            custom.insert(cat_keys::GEOHASH, CborValue::Array(cells));
+        }
+        if !self.cath.is_empty() {
+            custom.insert(cat_keys::CATH, CborValue::Bytes(cath_claim(&self.cath)));
+        }
+        if let Some(replay) = self.catreplay {
+            custom.insert(cat_keys::CATREPLAY, CborValue::Integer(replay.value()));
+        }
+        if let Some(version) = self.catv {
+            custom.insert(cat_keys::CATV, CborValue::Integer(version));
+        }
+        if let Some(catif) = &self.catif {
+            // the response is given for every claim the token is checked
+            // on, every token has an exp
+            let mut checked = vec![cose::EXP];
+            if self.nbf.is_some() {
+                checked.push(cose::NBF);
+            }
+            checked.extend(
+                CHECKED_CLAIMS
+                    .into_iter()
+                    .filter(|k| custom.contains_key(k)),
+            );
+            custom.insert(cat_keys::CATIF, CborValue::Bytes(catif.to_cbor(&checked)));
+        }
+        if let Some(data) = &self.catifdata {
+            custom.insert(cat_keys::CATIFDATA, CborValue::Text(data.clone()));
+        }
+        Ok(custom)
+    }
+}
+
+fn to_cbor(value: &ClaimValue) -> Result<CborValue, CatTesterError> {
+    Ok(match value {
+        ClaimValue::Integer(i) => CborValue::Integer(*i),
+        ClaimValue::Text(text) => CborValue::Text(text.clone()),
+        ClaimValue::Bytes(bytes) => CborValue::Bytes(decode_hex(&bytes.hex, "bytes")?),
+        ClaimValue::Array(items) => {
+            CborValue::Array(items.iter().map(to_cbor).collect::<Result<_, _>>()?)
+        }
+        ClaimValue::Map(map) => CborValue::Map(
+            map.iter()
+                .map(|(name, value)| Ok((map_key(name)?, to_cbor(value)?)))
+                .collect::<Result<_, CatTesterError>>()?,
+        ),
+    })
+}
+
+// The names of the CATR and CATU parameters do not overlap so a map key
+// can be looked up in all of them
+fn map_key(name: &str) -> Result<i32, CatTesterError> {
+    name.parse()
+        .ok()
+        .or_else(|| inspect::key_of(&RENEWAL_PARAMS, name))
+        .or_else(|| inspect::key_of(&URI_COMPONENTS, name))
+        .or_else(|| inspect::key_of(&MATCH_TYPES, name))
+        .ok_or_else(|| CatTesterError::Config(format!("unknown map key {}", name)))
+}
+
+fn decode_hex(s: &str, what: &str) -> Result<Vec<u8>, CatTesterError> {
+    hex::decode(s).map_err(|e| CatTesterError::Config(format!("{} {} is not hex: {}", what, s, e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common_access_token::uri_components;
+
+    #[test]
+    fn parse_claims_file() {
+        let toml: ClaimSet = toml::from_str(
+            r#"
+            sub = "user"
+            exp = 600
+            cti = "0a0b"
+            [claims]
+            catm = "GET,HEAD"
+            catnip = [{ hex = "0a000001" }]
+            catu = { path = { prefix = "/live/" }, extension = { exact = ".m3u8" } }
+            "#,
+        )
+        .unwrap();
+        let json: ClaimSet = serde_json::from_str(
+            r#"{"sub": "user", "exp": 600, "cti": "0a0b", "claims": {
+                "catm": "GET,HEAD",
+                "catnip": [{"hex": "0a000001"}],
+                "catu": {"path": {"prefix": "/live/"}, "extension": {"exact": ".m3u8"}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(toml, json);
+        assert_eq!(toml.cti().unwrap(), Some(vec![10, 11]));
+
+        let custom = toml.custom().unwrap();
+        assert_eq!(
+            custom[&cat_keys::CATM],
+            CborValue::Text(String::from("GET,HEAD"))
+        );
+        assert_eq!(
+            custom[&cat_keys::CATNIP],
+            CborValue::Array(vec![CborValue::Bytes(vec![10, 0, 0, 1])])
+        );
+        let CborValue::Map(catu) = &custom[&cat_keys::CATU] else {
+            panic!("catu is not a map");
+        };
+        assert_eq!(
+            catu[&uri_components::PATH],
+            CborValue::Map(BTreeMap::from([(
+                match_types::PREFIX,
+                CborValue::Text(String::from("/live/"))
+            )]))
+        );
+        assert!(catu.contains_key(&uri_components::EXTENSION));
+    }
+
+    #[test]
+    fn parse_uri_match() {
+        let m: UriMatch = "query:regex:^a=(.*)$".parse().unwrap();
+        assert_eq!(m.component, uri_components::QUERY);
+        assert_eq!(m.match_type, match_types::REGEX);
+        assert_eq!(m.value, "^a=(.*)$");
+        assert!("path:prefix".parse::<UriMatch>().is_err());
+        assert!("fragment:exact:x".parse::<UriMatch>().is_err());
+        assert!("path:near:/".parse::<UriMatch>().is_err());
+
+        let claims = toml::from_str::<ClaimSet>(
+            "catu = [\"path:prefix:/live/\", \"host:sha256:cdn.example.com\"]\n",
+        )
+        .unwrap()
+        .with_catu(vec!["path:suffix:.ts".parse().unwrap()]);
+        let custom = claims.custom().unwrap();
+        let CborValue::Map(catu) = &custom[&cat_keys::CATU] else {
+            panic!("catu is not a map");
+        };
+        assert_eq!(
+            catu[&uri_components::PATH],
+            CborValue::Map(BTreeMap::from([
+                (match_types::PREFIX, CborValue::Text(String::from("/live/"))),
+                (match_types::SUFFIX, CborValue::Text(String::from(".ts"))),
+            ]))
+        );
+        assert_eq!(
+            catu[&uri_components::HOST],
+            CborValue::Map(BTreeMap::from([(
+                match_types::SHA256,
+                CborValue::Bytes(Sha256::digest(b"cdn.example.com").to_vec())
+            )]))
+        );
+        // the claim accepts urls that match every component
+        let url = "http://cdn.example.com/live/segment_1.ts".parse().unwrap();
+        assert!(crate::verify::check_catu(&custom[&cat_keys::CATU], &url).is_ok());
+        let url = "http://cdn.example.com/vod/segment_1.ts".parse().unwrap();
+        assert!(crate::verify::check_catu(&custom[&cat_keys::CATU], &url).is_err());
+    }
+
+    #[test]
+    fn catm_from_file_or_command_line() {
+        let claims = toml::from_str::<ClaimSet>("catm = [\"get\", \"head\"]\n").unwrap();
+        assert_eq!(
+            claims.custom().unwrap()[&cat_keys::CATM],
+            CborValue::Text(String::from("GET,HEAD"))
+        );
+        let claims = claims
+            .with_catm(vec![])
+            .with_catm(vec![String::from("POST")]);
+        assert_eq!(claims.catm, vec![String::from("POST")]);
+    }
+
+    #[test]
+    fn catnip_networks() {
+        let claims = toml::from_str::<ClaimSet>(
+            "catnip = [\"10.1.2.3\", \"192.168.17.0/20\", \"2001:db8::/32\", \"AS64500\"]\n",
+        )
+        .unwrap();
+        let CborValue::Bytes(raw) = &claims.custom().unwrap()[&cat_keys::CATNIP] else {
+            panic!("catnip is not encoded");
+        };
+        // the host bits are cleared and trailing zero bytes dropped
+        assert_eq!(
+            cose::diagnostic(raw),
+            "[52([32, h'0a010203']), 52([20, h'c0a810']), 54([32, h'20010db8']), 64500]"
+        );
+
+        let catnip = &claims.custom().unwrap()[&cat_keys::CATNIP];
+        let ip = "192.168.31.1".parse().unwrap();
+        assert!(crate::verify::check_catnip(catnip, ip).is_ok());
+        let ip = "2001:db8:1::1".parse().unwrap();
+        assert!(crate::verify::check_catnip(catnip, ip).is_ok());
+        let ip = "10.1.2.4".parse().unwrap();
+        assert!(crate::verify::check_catnip(catnip, ip).is_err());
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+        assert!("ASx".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn geo_claims() {
+        let claims = toml::from_str::<ClaimSet>(
+            "catgeoiso3166 = [\"se\", \"US-CA\"]\ncatgeohash = [\"U6SC\"]\n",
+        )
+        .unwrap();
+        let custom = claims.custom().unwrap();
+        assert_eq!(
+            custom[&cat_keys::CATGEOISO3166],
+            CborValue::Array(vec![
+                CborValue::Text(String::from("SE")),
+                CborValue::Text(String::from("US-CA"))
+            ])
+        );
+        assert_eq!(
+            custom[&cat_keys::GEOHASH],
+            CborValue::Array(vec![CborValue::Text(String::from("u6sc"))])
+        );
+        let claims = claims.with_catgeoiso3166(vec![String::from("NO")]);
+        assert_eq!(claims.catgeoiso3166, vec![String::from("NO")]);
+    }
+
+    #[test]
+    fn catreplay_claim() {
+        let claims = toml::from_str::<ClaimSet>("catreplay = \"reuse-detection\"\n").unwrap();
+        assert_eq!(
+            claims.custom().unwrap()[&cat_keys::CATREPLAY],
+            CborValue::Integer(2)
+        );
+        let claims = claims.with_catreplay(None);
+        assert_eq!(claims.catreplay, Some(Replay::ReuseDetection));
+        let claims = claims.with_catreplay(Some(Replay::Prohibited));
+        assert_eq!(
+            claims.custom().unwrap()[&cat_keys::CATREPLAY],
+            CborValue::Integer(1)
+        );
+    }
+
+    #[test]
+    fn cath_claim_maps_header_names_to_matches() {
+        let claims = toml::from_str::<ClaimSet>("cath = [\"User-Agent:prefix:TestPlayer/\"]\n")
+            .unwrap()
+            .with_cath(vec![
+                "user-agent:suffix:(tv)".parse().unwrap(),
+                "Origin:exact:https://player.example.com".parse().unwrap(),
+            ]);
+        let CborValue::Bytes(raw) = &claims.custom().unwrap()[&cat_keys::CATH] else {
+            panic!("cath is not encoded");
+        };
+        assert_eq!(
+            cose::diagnostic(raw),
+            r#"{"User-Agent": {1: "TestPlayer/", 2: "(tv)"}, "Origin": {0: "https://player.example.com"}}"#
+        );
+        assert!(":exact:x".parse::<HeaderMatch>().is_err());
+        assert!("Origin:exact".parse::<HeaderMatch>().is_err());
+    }
+
+    #[test]
+    fn catv_and_catif_claims() {
+        let claims = toml::from_str::<ClaimSet>(
+            r#"
+            catv = 1
+            catifdata = "abc"
+            [catif]
+            status = 302
+            headers = ["Location: https://auth.example.com/?d={catifdata}"]
+            "#,
+        )
+        .unwrap();
+        let custom = claims.custom().unwrap();
+        assert_eq!(custom[&cat_keys::CATV], CborValue::Integer(1));
+        assert_eq!(
+            custom[&cat_keys::CATIFDATA],
+            CborValue::Text(String::from("abc"))
+        );
+        let CborValue::Bytes(catif) = &custom[&cat_keys::CATIF] else {
+            panic!("catif is not encoded");
+        };
+        let response = r#"[302, {"Location": "https://auth.example.com/?d={catifdata}"}]"#;
+        assert_eq!(
+            cose::diagnostic(catif),
+            format!("{{4: {}, 310: {}}}", response, response)
+        );
+
+        let claims = claims
+            .with_catv(Some(2))
+            .with_catif(Some(307), vec!["Location: /live/".parse().unwrap()]);
+        assert_eq!(claims.catv, Some(2));
+        assert_eq!(claims.catif.as_ref().unwrap().status, 307);
+        assert_eq!(claims.clone().with_catif(None, vec![]), claims);
+        assert!("Location".parse::<ResponseHeader>().is_err());
+    }
+
+    #[test]
+    fn renewal_settings_from_file_and_command_line() {
+        let claims = toml::from_str::<ClaimSet>(
+            r#"
+            [renewal]
+            type = "redirect"
+            exp-add = 60
+            code = 307
+            "#,
+        )
+        .unwrap()
+        .with_renewal(Renewal {
+            exp_add: Some(30),
+            deadline: Some(10),
+            ..Renewal::default()
+        });
+        assert_eq!(
+            claims.renewal,
+            Renewal {
+                kind: Some(RenewalType::Redirect),
+                exp_add: Some(30),
+                deadline: Some(10),
+                code: Some(307),
+                ..Renewal::default()
+            }
+        );
+        assert!(toml::from_str::<ClaimSet>("[renewal]\nkind = \"header\"\n").is_err());
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        let claims: ClaimSet = toml::from_str("[claims]\ncatx = 1\n").unwrap();
+        assert!(matches!(claims.custom(), Err(CatTesterError::Config(_))));
+        let claims: ClaimSet =
+            toml::from_str("[claims]\ncatu = { path = { near = \"/\" } }\n").unwrap();
+        assert!(matches!(claims.custom(), Err(CatTesterError::Config(_))));
+        // claims by number are passed on as they are
+        let claims: ClaimSet = toml::from_str("[claims]\n400 = 1\n").unwrap();
+        assert_eq!(claims.custom().unwrap()[&400], CborValue::Integer(1));
+        assert!(toml::from_str::<ClaimSet>("subject = \"x\"\n").is_err());
+    }
+}
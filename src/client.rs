@@ -1,25 +1,182 @@
+use crate::claims::ClaimSet;
+use crate::clock::Clock;
+use crate::dash::{self, Representation};
+use crate::error::CatTesterError;
+use crate::expect::Expectations;
+use crate::inspect;
+use crate::outcome::{Failure, Outcome, Redirect, RenewalClock, Report};
+use crate::playlist::{self, ByteRange, Map, MediaPlaylist, Playlist, Segment, Variant};
 use crate::token;
 use crate::token::TokenType;
+use crate::verify;
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use common_access_token::{CborValue, Token, cat_keys, renewal_params};
 use reqwest::Url;
 use reqwest::cookie::Jar;
-use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::header::{
+    ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, CONTENT_LENGTH, CONTENT_TYPE,
+    HeaderMap, HeaderValue, LOCATION, ORIGIN, RANGE, SET_COOKIE, USER_AGENT,
+};
+use reqwest::redirect::Policy;
+use std::collections::HashSet;
 use std::net::IpAddr;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::{thread, time::Duration};
+use std::time::Duration;
+
+/// Segments from the end of a live playlist to start at, like a player does
+const LIVE_EDGE_SEGMENTS: usize = 3;
+/// Never reload a live playlist more often than this
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_millis(100);
+/// Name of the token header and cookie
+const TOKEN_NAME: &str = "CTA-Common-Access-Token";
+/// Query parameter with the token, see TokenType::CookieAsQuery and TokenType::Query
+const TOKEN_QUERY: &str = "CAT";
+/// Origin of the player page in a CORS preflight, the CDN is another origin
+const PREFLIGHT_ORIGIN: &str = "https://player.example.com";
+/// Redirects followed for one request, the same limit as the reqwest default policy
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum Mode {
+    /// Fetch the first segment in the playlist max_iterations times
+    Repeat,
+    /// Reload the playlist every target duration and fetch each new segment once
+    Live,
+    /// Fetch the init segment and every segment from first to last, paced by
+    /// the segment duration divided by speed
+    Vod,
+}
+
+/// Method for segment, init and key requests, playlists are always fetched with GET
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RequestMethod {
+    Get,
+    Head,
+    /// CORS preflight like a browser sends before a cross origin request,
+    /// without the token and not checked for renewal
+    Options,
+    /// Post with an empty body
+    Post,
+}
+
+impl From<RequestMethod> for reqwest::Method {
+    fn from(method: RequestMethod) -> Self {
+        match method {
+            RequestMethod::Get => reqwest::Method::GET,
+            RequestMethod::Head => reqwest::Method::HEAD,
+            RequestMethod::Options => reqwest::Method::OPTIONS,
+            RequestMethod::Post => reqwest::Method::POST,
+        }
+    }
+}
+
+/// How to pick a variant when the url points at a master playlist or a DASH manifest
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariantSelector {
+    Highest,
+    Lowest,
+    /// Variant with the bandwidth closest to the given value
+    Bandwidth(u64),
+    /// First variant whose uri, representation id or resolution contains the given text
+    Name(String),
+}
+
+impl FromStr for VariantSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(String::from("variant selector can not be empty"));
+        }
+        let selector = match s.to_lowercase().as_str() {
+            "highest" => VariantSelector::Highest,
+            "lowest" => VariantSelector::Lowest,
+            other => match other.parse::<u64>() {
+                Ok(bandwidth) => VariantSelector::Bandwidth(bandwidth),
+                Err(_) => VariantSelector::Name(s.to_string()),
+            },
+        };
+        Ok(selector)
+    }
+}
+
+/// What a request fetches, playlists, init segments and keys are reported with their url
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Resource {
+    Playlist,
+    Segment,
+    Init,
+    Key,
+}
+
+// State for one run, headers carries the renewed token between requests
+// and token is the token that is sent. A token renewed by a redirect is kept
+// in query_token and sent in the query of every request after it
+struct Session {
+    headers: HeaderMap,
+    token: Token,
+    query_token: Option<String>,
+    requests: u32,
+    init: Option<Map>,
+    keys: HashSet<String>,
+    clock: RenewalClock,
+    outcomes: Vec<Outcome>,
+}
+
+impl Session {
+    fn new(headers: HeaderMap, token: Token, clock: RenewalClock) -> Self {
+        Self {
+            headers,
+            token,
+            query_token: None,
+            requests: 0,
+            init: None,
+            keys: HashSet::new(),
+            clock,
+            outcomes: Vec::new(),
+        }
+    }
+
+    // The url to request, with the token from a redirect renewal
+    fn request_url(&self, url: &str) -> Result<String, CatTesterError> {
+        match &self.query_token {
+            Some(token) => with_query_token(url, token),
+            None => Ok(url.to_string()),
+        }
+    }
+}
 
 pub struct Worker {
-    key: String,
+    // loaded once, tokens are signed and renewed tokens checked with it
+    key: token::Key,
     url: String,
     issuer: String,
     ttl: u64,
     token_type: TokenType,
     host: Url,
-    cookie_domain: Option<String>,
+    cookie_domain: String,
     max_iterations: u32,
     http_client: reqwest::Client,
+    // client without cookies for the preflight of an OPTIONS run
+    preflight_client: reqwest::Client,
+    // base64url encoded token sent in the first request
+    token: String,
     sleep: u64,
+    variant: VariantSelector,
+    mode: Mode,
+    duration: Option<Duration>,
+    speed: f64,
+    keep_query: bool,
+    expectations: Expectations,
+    claims: ClaimSet,
+    method: RequestMethod,
+    // address the connections are made from, any address if None
+    local_address: Option<IpAddr>,
+    // sent with every playlist, segment, init and key request
+    headers: HeaderMap,
+    clock: Clock,
 }
 
 impl Worker {
@@ -31,135 +188,846 @@ impl Worker {
         issuer: &str,
         max_iterations: u32,
         sleep: u64,
-    ) -> Self {
-        let u = url.parse::<Url>().unwrap();
+    ) -> Result<Self, CatTesterError> {
+        let u = url
+            .parse::<Url>()
+            .map_err(|e| CatTesterError::Url(format!("{}: {}", url, e)))?;
         let scheme = u.scheme();
         let host = u.host_str().unwrap_or("localhost");
-        let cookie_domain = Self::extract_cookie_domain(&host);
-        let host = format!("{}://{}", scheme, host).parse::<Url>().unwrap();
+        let cookie_domain = Self::extract_cookie_domain(host).unwrap_or_else(|| host.to_string());
+        let host = format!("{}://{}", scheme, host)
+            .parse::<Url>()
+            .map_err(|e| CatTesterError::Url(format!("{}: {}", url, e)))?;
 
         let runner = Self {
             http_client: reqwest::Client::new(), // temporary, will be replaced
+            preflight_client: reqwest::Client::new(),
+            token: String::new(),
             url: String::from(url),
             token_type,
-            key: String::from(key),
+            key: token::load_key(key)?,
             ttl,
             cookie_domain,
             issuer: String::from(issuer),
             host,
             max_iterations,
             sleep,
+            variant: VariantSelector::Highest,
+            mode: Mode::Repeat,
+            duration: None,
+            speed: 1.0,
+            keep_query: false,
+            expectations: Expectations::default(),
+            claims: ClaimSet::default(),
+            method: RequestMethod::Get,
+            local_address: None,
+            headers: HeaderMap::new(),
+            clock: Clock::default(),
         };
-        let client = runner
-            .create_http_client()
-            .expect("Failed to create HTTP client");
+        runner.with_token()
+    }
 
-        Self {
+    // Create the token and the http client that sends it
+    fn with_token(self) -> Result<Self, CatTesterError> {
+        let runner = Self {
+            token: self.encoded_token()?,
+            ..self
+        };
+        let client = runner.create_http_client()?;
+        let preflight_client = runner.create_preflight_client()?;
+
+        Ok(Self {
             http_client: client,
+            preflight_client,
             ..runner
+        })
+    }
+
+    /// Select which variant to follow when the url is a master playlist
+    pub fn with_variant(self, variant: VariantSelector) -> Self {
+        Self { variant, ..self }
+    }
+
+    pub fn with_mode(self, mode: Mode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Stop a live or vod run after this time even if it is not done
+    pub fn with_duration(self, duration: Option<Duration>) -> Self {
+        Self { duration, ..self }
+    }
+
+    /// Playback speed in vod mode, 0 fetches segments without waiting
+    pub fn with_speed(self, speed: f64) -> Self {
+        Self { speed, ..self }
+    }
+
+    /// Add the query parameters of the playlist url to segment, init, key and variant urls
+    pub fn with_keep_query(self, keep_query: bool) -> Self {
+        Self { keep_query, ..self }
+    }
+
+    /// Expected status and renewal for the requests in the run
+    pub fn with_expectations(self, expectations: Expectations) -> Self {
+        Self {
+            expectations,
+            ..self
         }
     }
 
-    pub async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Method for segment, init and key requests
+    pub fn with_method(self, method: RequestMethod) -> Self {
+        Self { method, ..self }
+    }
+
+    /// Make connections from this local address, for a token that is only
+    /// valid from some networks
+    pub fn with_local_address(self, local_address: Option<IpAddr>) -> Result<Self, CatTesterError> {
+        let runner = Self {
+            local_address,
+            ..self
+        };
+        Ok(Self {
+            http_client: runner.create_http_client()?,
+            preflight_client: runner.create_preflight_client()?,
+            ..runner
+        })
+    }
+
+    /// Headers to send with every request, they replace the default User-Agent
+    pub fn with_headers(self, headers: HeaderMap) -> Self {
+        Self { headers, ..self }
+    }
+
+    /// Clock for the token times, the renewal deadlines and the waits
+    /// between requests, the token is created again at its time
+    #[cfg(test)]
+    pub fn with_clock(self, clock: Clock) -> Result<Self, CatTesterError> {
+        Self { clock, ..self }.with_token()
+    }
+
+    /// A worker with the same settings and first token but its own HTTP
+    /// client and cookie jar, like a second player that copied the token
+    pub fn independent(&self) -> Result<Self, CatTesterError> {
+        Ok(Self {
+            key: self.key.clone(),
+            url: self.url.clone(),
+            issuer: self.issuer.clone(),
+            ttl: self.ttl,
+            token_type: self.token_type.clone(),
+            host: self.host.clone(),
+            cookie_domain: self.cookie_domain.clone(),
+            max_iterations: self.max_iterations,
+            http_client: self.create_http_client()?,
+            preflight_client: self.preflight_client.clone(),
+            token: self.token.clone(),
+            sleep: self.sleep,
+            variant: self.variant.clone(),
+            mode: self.mode.clone(),
+            duration: self.duration,
+            speed: self.speed,
+            keep_query: self.keep_query,
+            expectations: self.expectations.clone(),
+            claims: self.claims.clone(),
+            method: self.method,
+            local_address: self.local_address,
+            headers: self.headers.clone(),
+            clock: self.clock.clone(),
+        })
+    }
+
+    /// The base64url token sent in the first request
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Claims of the token, the token is created again with them
+    pub fn with_claims(self, claims: ClaimSet) -> Result<Self, CatTesterError> {
+        Self { claims, ..self }.with_token()
+    }
+
+    /// Fetch the playlist and its segments, the report has the outcome of
+    /// every playlist, segment, init and key request
+    pub async fn run(&self) -> Result<Report, CatTesterError> {
+        let issued = self.clock.now();
         let mut headers = HeaderMap::new();
         headers.insert(USER_AGENT, HeaderValue::from_static("reqwest"));
+        headers.extend(self.headers.clone());
         let mut manifest_url = self.url.clone();
         if self.token_type == TokenType::Header {
-            let token_header = HeaderValue::from_str(&self.encoded_token().unwrap())?;
-            headers.insert("CTA-Common-Access-Token", token_header);
+            let token_header = HeaderValue::from_str(&self.token)
+                .map_err(|e| CatTesterError::Token(e.to_string()))?;
+            headers.insert(TOKEN_NAME, token_header);
         } else if self.token_type == TokenType::CookieAsQuery {
-            let params = [("CAT", &self.encoded_token().unwrap())];
-            let parsed_url = reqwest::Url::parse_with_params(&manifest_url, &params)?;
-            manifest_url = parsed_url.to_string();
-        }
-        let result = self
-            .http_client
-            .get(&manifest_url)
-            .headers(headers.clone())
-            .send()
+            manifest_url = with_query_token(&manifest_url, &self.token)?;
+        }
+        let token = inspect::decode(&self.token)?;
+        let clock = renewal_clock(issued, &token, self.ttl);
+        let mut session = Session::new(headers, token, clock);
+        if self.token_type == TokenType::Query {
+            session.query_token = Some(self.token.clone());
+        }
+        let manifest_url = session.request_url(&manifest_url)?;
+        let Some((playlist_url, media_playlist)) = self
+            .fetch_playlist(&mut session, &manifest_url, &self.url)
+            .await?
+        else {
+            eprintln!("Playlist request failed");
+            return Ok(Report {
+                outcomes: session.outcomes,
+            });
+        };
+        match self.mode {
+            Mode::Repeat => {
+                self.repeat_first_segment(&mut session, &playlist_url, &media_playlist)
+                    .await
+            }
+            Mode::Live => {
+                self.play_live(&mut session, &playlist_url, media_playlist)
+                    .await
+            }
+            Mode::Vod => {
+                self.play_vod(&mut session, &playlist_url, &media_playlist)
+                    .await
+            }
+        }?;
+        Ok(Report {
+            outcomes: session.outcomes,
+        })
+    }
+
+    async fn repeat_first_segment(
+        &self,
+        session: &mut Session,
+        playlist_url: &str,
+        media_playlist: &MediaPlaylist,
+    ) -> Result<(), CatTesterError> {
+        let segment = media_playlist.segments.first().ok_or_else(|| {
+            CatTesterError::Playlist(String::from("No segments found in media playlist"))
+        })?;
+        let stream_url = self.resolve(playlist_url, &segment.uri)?;
+        self.fetch_dependencies(session, playlist_url, segment)
             .await?;
-        let body = result.text().await?;
-        let stream_segment = find_line_after_pattern(&body, "EXTINF").unwrap();
-        // Handle that the segments can be a full url or a path segment
-        let stream_url = if stream_segment.starts_with("http") {
-            stream_segment
+
+        for _ in 0..self.max_iterations {
+            self.fetch(
+                session,
+                Resource::Segment,
+                &stream_url,
+                segment.byte_range.as_ref(),
+            )
+            .await?;
+            if self.sleep > 0 {
+                self.clock.sleep(Duration::from_millis(self.sleep)).await;
+            }
+        }
+        Ok(())
+    }
+
+    // Reload the playlist like a live player and fetch every new segment once,
+    // the renewed token is used for both playlist and segments
+    async fn play_live(
+        &self,
+        session: &mut Session,
+        playlist_url: &str,
+        mut media_playlist: MediaPlaylist,
+    ) -> Result<(), CatTesterError> {
+        let started = self.clock.now();
+        let mut fetched = 0;
+        let start = media_playlist
+            .segments
+            .len()
+            .saturating_sub(LIVE_EDGE_SEGMENTS);
+        let mut next_sequence = media_playlist
+            .segments
+            .get(start)
+            .map_or(media_playlist.media_sequence, |s| s.sequence);
+        loop {
+            if let Some(first) = media_playlist.segments.first()
+                && first.sequence > next_sequence
+            {
+                eprintln!(
+                    "Behind the live edge, {} segments no longer in the playlist",
+                    first.sequence - next_sequence
+                );
+            }
+            let mut new_segments = false;
+            let first_new = next_sequence;
+            for segment in media_playlist
+                .segments
+                .iter()
+                .filter(|s| s.sequence >= first_new)
+            {
+                if self.finished(fetched, started) {
+                    return Ok(());
+                }
+                fetched += 1;
+                self.fetch_dependencies(session, playlist_url, segment)
+                    .await?;
+                let stream_url = self.resolve(playlist_url, &segment.uri)?;
+                self.fetch(
+                    session,
+                    Resource::Segment,
+                    &stream_url,
+                    segment.byte_range.as_ref(),
+                )
+                .await?;
+                next_sequence = segment.sequence + 1;
+                new_segments = true;
+            }
+            if media_playlist.end_list {
+                eprintln!("Playlist ended after {} segments", fetched);
+                return Ok(());
+            }
+            if self.finished(fetched, started) {
+                return Ok(());
+            }
+            // RFC 8216 6.3.4, wait half the target duration if the playlist did not change
+            let mut wait = Duration::from_secs(media_playlist.target_duration);
+            if !new_segments {
+                wait /= 2;
+            }
+            self.clock.sleep(wait.max(MIN_RELOAD_INTERVAL)).await;
+            let request_url = session.request_url(playlist_url)?;
+            // a player stops when the playlist can not be reloaded
+            let Some((_, reloaded)) = self
+                .fetch_playlist(session, &request_url, playlist_url)
+                .await?
+            else {
+                eprintln!("Playlist reload failed after {} segments", fetched);
+                return Ok(());
+            };
+            media_playlist = reloaded;
+        }
+    }
+
+    // Play the whole playlist from start to end
+    async fn play_vod(
+        &self,
+        session: &mut Session,
+        playlist_url: &str,
+        media_playlist: &MediaPlaylist,
+    ) -> Result<(), CatTesterError> {
+        let started = self.clock.now();
+        let mut media_time = 0.0;
+        let mut played = 0;
+        for segment in &media_playlist.segments {
+            if self
+                .duration
+                .is_some_and(|d| self.clock.elapsed(started) >= d)
+            {
+                eprintln!("Duration reached");
+                break;
+            }
+            self.fetch_dependencies(session, playlist_url, segment)
+                .await?;
+            let stream_url = self.resolve(playlist_url, &segment.uri)?;
+            self.fetch(
+                session,
+                Resource::Segment,
+                &stream_url,
+                segment.byte_range.as_ref(),
+            )
+            .await?;
+            played += 1;
+            media_time += segment.duration;
+            // keep to the media timeline instead of sleeping a fixed time per segment
+            if self.speed > 0.0 && played < media_playlist.segments.len() {
+                let due = Duration::from_secs_f64(media_time / self.speed);
+                if let Some(wait) = due.checked_sub(self.clock.elapsed(started)) {
+                    self.clock.sleep(wait).await;
+                }
+            }
+        }
+        eprintln!(
+            "Played {} of {} segments, {:.1}s of {:.1}s media in {:.1}s",
+            played,
+            media_playlist.segments.len(),
+            media_time,
+            media_playlist.duration(),
+            self.clock.elapsed(started).as_secs_f64()
+        );
+        Ok(())
+    }
+
+    fn finished(&self, fetched: u32, started: Duration) -> bool {
+        fetched >= self.max_iterations
+            || self
+                .duration
+                .is_some_and(|d| self.clock.elapsed(started) >= d)
+    }
+
+    // Fetch the init segment and the keys for a segment unless they are
+    // already fetched, a player only fetches them again when they change
+    async fn fetch_dependencies(
+        &self,
+        session: &mut Session,
+        playlist_url: &str,
+        segment: &Segment,
+    ) -> Result<(), CatTesterError> {
+        if let Some(map) = &segment.map
+            && session.init.as_ref() != Some(map)
+        {
+            let init_url = self.resolve(playlist_url, &map.uri)?;
+            self.fetch(session, Resource::Init, &init_url, map.byte_range.as_ref())
+                .await?;
+            session.init = Some(map.clone());
+        }
+        for key in &segment.keys {
+            // data: and skd: keys are handled by the DRM system, not the CDN
+            let Some(uri) = key.uri.as_deref().filter(|uri| is_http_uri(uri)) else {
+                continue;
+            };
+            let key_url = self.resolve(playlist_url, uri)?;
+            if session.keys.insert(key_url.clone()) {
+                self.fetch(session, Resource::Key, &key_url, None).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch(
+        &self,
+        session: &mut Session,
+        resource: Resource,
+        url: &str,
+        byte_range: Option<&ByteRange>,
+    ) -> Result<(), CatTesterError> {
+        session.requests += 1;
+        let sent = self.clock.now();
+        let (res, redirects) = if self.method == RequestMethod::Options {
+            let request_url = session.request_url(url)?;
+            (
+                self.preflight_request(&request_url).send().await?,
+                Vec::new(),
+            )
+        } else {
+            let mut headers = session.headers.clone();
+            if let Some(range) = byte_range {
+                let value = HeaderValue::from_str(&range.header_value())
+                    .map_err(|e| CatTesterError::Playlist(e.to_string()))?;
+                headers.insert(RANGE, value);
+            }
+            self.send(self.method.into(), &session.request_url(url)?, &headers)
+                .await?
+        };
+        self.record(session, resource, url, &res, redirects, sent)
+    }
+
+    // Check the response to the last request in the session against the
+    // expectations and the renewal clock and add its outcome, a renewed
+    // token is used from the next request
+    fn record(
+        &self,
+        session: &mut Session,
+        resource: Resource,
+        url: &str,
+        res: &reqwest::Response,
+        redirects: Vec<Redirect>,
+        sent: Duration,
+    ) -> Result<(), CatTesterError> {
+        let received = self.clock.now();
+        // a preflight has no token so there is nothing to renew, playlists
+        // are always fetched with GET
+        let preflight = self.method == RequestMethod::Options && resource != Resource::Playlist;
+        let (response_token, redirect_token) = if preflight {
+            (None, None)
+        } else {
+            (
+                self.renewed_token(&session.token, res.headers()),
+                redirect_token(&redirects),
+            )
+        };
+        if self.token_type == TokenType::Header && !preflight {
+            match &response_token {
+                Some(token) => {
+                    let token = HeaderValue::from_str(token)
+                        .map_err(|e| CatTesterError::Token(e.to_string()))?;
+                    session.headers.insert(TOKEN_NAME, token);
+                }
+                None if redirect_token.is_none() => {
+                    eprintln!("No token found");
+                    eprintln!("Headers: {:#?}\n", res.headers());
+                }
+                None => {}
+            }
+        }
+        if let Some(token) = &redirect_token {
+            session.query_token = Some(token.clone());
+        }
+        let renewed_token = redirect_token.or(response_token);
+        let renewed = renewed_token.is_some();
+        let mut timing = if preflight {
+            Vec::new()
         } else {
-            replace_last_path_segment(&self.url, &stream_segment)
+            session.clock.check(sent, received, renewed)
+        };
+        if let Some(renewed_token) = renewed_token {
+            timing.extend(self.check_renewal(session, &renewed_token)?);
+        }
+        let renewed_so_far = renewed || session.outcomes.iter().any(|o| o.renewed);
+        let failures = self.expectations.check(
+            session.requests,
+            res.status().as_u16(),
+            renewed_so_far,
+            timing,
+        );
+        let label = match resource {
+            Resource::Playlist => format!(" playlist {}", url),
+            Resource::Segment => String::new(),
+            Resource::Init => format!(" init {}", url),
+            Resource::Key => format!(" key {}", url),
         };
+        // a chunked or compressed response has no content-length, the size is unknown
+        let content_length = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .unwrap_or("unknown");
+        for redirect in &redirects {
+            eprintln!("Req: {} redirect {}", session.requests, redirect);
+        }
+        eprintln!(
+            "Req: {}{}, Response: {}, content-length: {}",
+            session.requests,
+            label,
+            res.status(),
+            content_length
+        );
+        if failures.is_empty() {
+            eprintln!("Req: {} PASS", session.requests);
+        } else {
+            let reasons: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+            eprintln!("Req: {} FAIL: {}", session.requests, reasons.join(", "));
+        }
+        session.outcomes.push(Outcome {
+            request: session.requests,
+            url: url.to_string(),
+            status: res.status().as_u16(),
+            renewed,
+            redirects,
+            failures,
+        });
+        // the token in the query of the first playlist request is moved into
+        // a cookie, later requests have no token without it
+        if self.token_type == TokenType::CookieAsQuery
+            && resource == Resource::Playlist
+            && session.requests == 1
+            && res.status().is_success()
+            && !renewed
+        {
+            return Err(CatTesterError::MissingHeader(format!(
+                "Set-Cookie with the token in response {} to request {}",
+                res.status(),
+                session.requests
+            )));
+        }
+        Ok(())
+    }
 
-        for i in 1..self.max_iterations + 1 {
+    // Send a request and follow redirects like the reqwest default policy
+    // does, the client does not follow them itself so every Location header,
+    // like the one in a CATIF response, is seen and recorded
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> Result<(reqwest::Response, Vec<Redirect>), CatTesterError> {
+        let mut method = method;
+        let mut url =
+            Url::parse(url).map_err(|e| CatTesterError::Url(format!("{}: {}", url, e)))?;
+        let mut redirects = Vec::new();
+        loop {
             let res = self
                 .http_client
-                .get(&stream_url)
+                .request(method.clone(), url.clone())
                 .headers(headers.clone())
                 .send()
                 .await?;
-            if self.token_type == TokenType::Header {
-                match res.headers().get("cta-common-access-token") {
-                    Some(token) => {
-                        headers.try_insert("cta-common-access-token", token.clone())?;
-                    }
-                    None => {
-                        eprintln!("No token found");
-                        eprintln!("Headers: {:#?}\n", res.headers());
-                    }
-                }
+            let status = res.status();
+            let location = res
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok());
+            let Some(location) = location.filter(|_| status.is_redirection()) else {
+                return Ok((res, redirects));
+            };
+            if redirects.len() >= MAX_REDIRECTS {
+                return Err(CatTesterError::Url(format!(
+                    "more than {} redirects from {}",
+                    MAX_REDIRECTS,
+                    redirects
+                        .first()
+                        .map_or(url.as_str(), |r: &Redirect| &r.url)
+                )));
             }
-            eprintln!(
-                "Req: {}, Response: {}, content-length: {:?}",
-                i,
-                res.status(),
-                res.headers().get("content-length").unwrap()
-            );
-            if self.sleep > 0 {
-                thread::sleep(Duration::from_millis(self.sleep));
+            let next = url.join(location).map_err(|e| {
+                CatTesterError::Url(format!("Location {} from {}: {}", location, url, e))
+            })?;
+            redirects.push(Redirect {
+                status: status.as_u16(),
+                url: url.to_string(),
+                location: location.to_string(),
+            });
+            // RFC 9110 15.4, a POST is changed to a GET like browsers do
+            if status == reqwest::StatusCode::SEE_OTHER
+                || (method == reqwest::Method::POST
+                    && matches!(
+                        status,
+                        reqwest::StatusCode::MOVED_PERMANENTLY | reqwest::StatusCode::FOUND
+                    ))
+            {
+                method = reqwest::Method::GET;
             }
+            url = next;
+        }
+    }
+
+    // A browser sends the preflight without cookies or the token header and
+    // asks if the token header may be sent
+    fn preflight_request(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .preflight_client
+            .request(reqwest::Method::OPTIONS, url)
+            .header(ORIGIN, PREFLIGHT_ORIGIN)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "GET");
+        match self.token_type {
+            TokenType::Header => request.header(ACCESS_CONTROL_REQUEST_HEADERS, TOKEN_NAME),
+            TokenType::Cookie | TokenType::CookieAsQuery | TokenType::Query => request,
+        }
+    }
+
+    // The renewed token in a response, from the renewal header or the
+    // Set-Cookie named in the CATR claim of the token that was sent, the
+    // cookie jar picks up a renewed cookie by itself
+    fn renewed_token(&self, token: &Token, headers: &HeaderMap) -> Option<String> {
+        let catr = match token.claims.custom.get(&cat_keys::CATR) {
+            Some(CborValue::Map(catr)) => Some(catr),
+            _ => None,
+        };
+        let name = |param| match catr.and_then(|catr| catr.get(&param)) {
+            Some(CborValue::Text(name)) => name.as_str(),
+            _ => TOKEN_NAME,
+        };
+        match self.token_type {
+            // header parameters follow the token after a ;
+            TokenType::Header => headers
+                .get(name(renewal_params::HEADER_NAME))
+                .and_then(|token| token.to_str().ok())
+                .and_then(|token| token.split(';').next())
+                .map(|token| token.trim().to_string()),
+            TokenType::Cookie | TokenType::CookieAsQuery => {
+                let cookie_name = name(renewal_params::COOKIE_NAME);
+                headers
+                    .get_all(SET_COOKIE)
+                    .iter()
+                    .filter_map(|cookie| cookie.to_str().ok())
+                    .filter_map(|cookie| cookie.split(';').next()?.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case(cookie_name))
+                    .map(|(_, value)| value.trim().to_string())
+            }
+            // only renewed by a redirect, see redirect_token
+            TokenType::Query => None,
         }
-        Ok(())
     }
 
-    fn encoded_token(&self) -> Option<String> {
+    // Decode and verify a renewed token against the token it replaces, a
+    // valid renewal becomes the token that is checked next time
+    fn check_renewal(
+        &self,
+        session: &mut Session,
+        renewed_token: &str,
+    ) -> Result<Vec<Failure>, CatTesterError> {
+        let checked = inspect::token_bytes(renewed_token).and_then(|renewed| {
+            verify::check_renewal(&session.token, &renewed, &self.key, self.clock.timestamp())
+        });
+        let (renewed, violations) = match checked {
+            Ok(checked) => checked,
+            Err(e) => return Ok(vec![Failure::InvalidRenewal(e.to_string())]),
+        };
+        session.token = renewed;
+        Ok(violations
+            .into_iter()
+            .map(Failure::InvalidRenewal)
+            .collect())
+    }
+
+    // Fetch a HLS playlist or DASH manifest from request_url, relative uris
+    // are resolved against url. Returns the url of the media playlist
+    // together with the parsed playlist, or None if a playlist request did
+    // not get a success status, the response is in the session outcomes
+    async fn fetch_playlist(
+        &self,
+        session: &mut Session,
+        request_url: &str,
+        url: &str,
+    ) -> Result<Option<(String, MediaPlaylist)>, CatTesterError> {
+        let Some((url, result)) = self.load_playlist(session, request_url, url).await? else {
+            return Ok(None);
+        };
+        let content_type = result
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|c| c.to_str().ok())
+            .map(String::from);
+        let body = result.text().await?;
+        if dash::is_mpd(content_type.as_deref(), &body) {
+            Ok(Some((url, self.dash_playlist(&body)?)))
+        } else {
+            self.media_playlist(session, &url, &body).await
+        }
+    }
+
+    // Follow a master playlist down to the selected variant, returns the url
+    // of the media playlist together with the parsed playlist
+    async fn media_playlist(
+        &self,
+        session: &mut Session,
+        url: &str,
+        body: &str,
+    ) -> Result<Option<(String, MediaPlaylist)>, CatTesterError> {
+        match playlist::parse(body)? {
+            Playlist::Media(media) => Ok(Some((url.to_string(), media))),
+            Playlist::Master(master) => {
+                let variant = select_variant(&master.variants, &self.variant).ok_or_else(|| {
+                    CatTesterError::Playlist(String::from(
+                        "No variant in master playlist matches the selector",
+                    ))
+                })?;
+                let playlist_url = self.resolve(url, &variant.uri)?;
+                eprintln!(
+                    "Master playlist, using variant: {} (bandwidth: {})",
+                    playlist_url, variant.bandwidth
+                );
+                let request_url = session.request_url(&playlist_url)?;
+                let Some((playlist_url, result)) = self
+                    .load_playlist(session, &request_url, &playlist_url)
+                    .await?
+                else {
+                    return Ok(None);
+                };
+                let body = result.text().await?;
+                match playlist::parse(&body)? {
+                    Playlist::Media(media) => Ok(Some((playlist_url, media))),
+                    Playlist::Master(_) => Err(CatTesterError::Playlist(String::from(
+                        "Variant uri points to a master playlist",
+                    ))),
+                }
+            }
+        }
+    }
+
+    // Send a playlist request and record its outcome like a segment request.
+    // Returns the url to resolve relative uris against together with the
+    // response, None if the status is not a success
+    async fn load_playlist(
+        &self,
+        session: &mut Session,
+        request_url: &str,
+        url: &str,
+    ) -> Result<Option<(String, reqwest::Response)>, CatTesterError> {
+        session.requests += 1;
+        let sent = self.clock.now();
+        let (result, redirects) = self
+            .send(reqwest::Method::GET, request_url, &session.headers)
+            .await?;
+        // a player resolves relative uris against the url it was redirected to
+        let base_url = if redirects.is_empty() {
+            url.to_string()
+        } else {
+            result.url().to_string()
+        };
+        self.record(session, Resource::Playlist, url, &result, redirects, sent)?;
+        if result.status().is_success() {
+            Ok(Some((base_url, result)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Expand the selected representation in a DASH manifest into a media playlist
+    fn dash_playlist(&self, body: &str) -> Result<MediaPlaylist, CatTesterError> {
+        let mpd = dash::parse(body, self.clock.timestamp())?;
+        let representations = mpd.video_representations();
+        let id = select_variant(&representations, &self.variant)
+            .map(|r| r.id.clone())
+            .ok_or_else(|| {
+                CatTesterError::Playlist(String::from(
+                    "No representation in mpd matches the selector",
+                ))
+            })?;
+        let representation = mpd
+            .representations
+            .into_iter()
+            .find(|r| r.id == id)
+            .ok_or_else(|| {
+                CatTesterError::Playlist(String::from(
+                    "No representation in mpd matches the selector",
+                ))
+            })?;
+        eprintln!(
+            "DASH manifest, using representation: {} (bandwidth: {})",
+            representation.id, representation.bandwidth
+        );
+        Ok(representation.playlist)
+    }
+
+    fn resolve(&self, base: &str, uri: &str) -> Result<String, CatTesterError> {
+        resolve_uri(base, uri, self.keep_query)
+    }
+
+    fn encoded_token(&self) -> Result<String, CatTesterError> {
         let token_bytes = token::create_token(
             &self.key,
             self.ttl,
             &self.token_type,
-            self.cookie_domain.as_ref().unwrap(),
+            &self.cookie_domain,
             &self.issuer,
-        );
-        Some(URL_SAFE_NO_PAD.encode(&token_bytes))
+            &self.claims,
+            self.clock.timestamp(),
+        )?;
+        Ok(URL_SAFE_NO_PAD.encode(&token_bytes))
     }
 
-    fn create_http_client(&self) -> reqwest::Result<reqwest::Client> {
-        match self.token_type {
+    fn create_http_client(&self) -> Result<reqwest::Client, CatTesterError> {
+        let builder = match self.token_type {
             TokenType::Cookie => {
-                let token = self.encoded_token().unwrap();
                 let cookie_str = format!(
-                    "CTA-Common-Access-Token={value}; Domain={domain}; Path=/",
-                    value = token,
-                    domain = self.cookie_domain.as_ref().unwrap()
+                    "{name}={value}; Domain={domain}; Path=/",
+                    name = TOKEN_NAME,
+                    value = self.token,
+                    domain = self.cookie_domain
                 );
                 let cookie_store = Arc::new(Jar::default());
                 cookie_store.add_cookie_str(&cookie_str, &self.host);
-                reqwest::Client::builder()
-                    .cookie_provider(cookie_store)
-                    .build()
+                reqwest::Client::builder().cookie_provider(cookie_store)
             }
             TokenType::CookieAsQuery => {
                 // create empty cookie jar
                 let cookie_store = Arc::new(Jar::default());
-                reqwest::Client::builder()
-                    .cookie_provider(cookie_store)
-                    .build()
+                reqwest::Client::builder().cookie_provider(cookie_store)
             }
-            TokenType::Header => reqwest::Client::builder().build(),
-        }
+            TokenType::Header | TokenType::Query => reqwest::Client::builder(),
+        };
+        Ok(builder
+            .local_address(self.local_address)
+            .redirect(Policy::none())
+            .build()?)
+    }
+
+    fn create_preflight_client(&self) -> Result<reqwest::Client, CatTesterError> {
+        Ok(reqwest::Client::builder()
+            .local_address(self.local_address)
+            .redirect(Policy::none())
+            .build()?)
     }
 
     fn is_ip(s: &str) -> bool {
         s.parse::<IpAddr>().is_ok()
     }
 
-    fn extract_cookie_domain(host: &str) -> Option<String> {
+    pub fn extract_cookie_domain(host: &str) -> Option<String> {
         if Self::is_ip(host) {
             return Some(host.to_string());
         }
@@ -173,29 +1041,173 @@ impl Worker {
     }
 }
 
-fn replace_last_path_segment(url: &str, path: &str) -> String {
-    match url.rfind('/') {
-        Some(pos) => format!("{}{}", &url[..=pos], path),
-        None => path.to_string(),
+// Renewal times from the CATR claim and exp of the token, counted from iat.
+// A token without a CATR claim gets the times of a token made by the tester
+fn renewal_clock(issued: Duration, token: &Token, ttl: u64) -> RenewalClock {
+    let Some(CborValue::Map(catr)) = token.claims.custom.get(&cat_keys::CATR) else {
+        return RenewalClock::new(issued, ttl);
+    };
+    let registered = &token.claims.registered;
+    let iat = registered.iat.unwrap_or_default();
+    let param = |param| match catr.get(&param) {
+        Some(CborValue::Integer(value)) => u64::try_from(*value).ok(),
+        _ => None,
+    };
+    let renew_after = param(renewal_params::DEADLINE).map_or(ttl / 2, |d| d.saturating_sub(iat));
+    let exp_add = param(renewal_params::EXPADD).unwrap_or(ttl);
+    let expires_after = registered
+        .exp
+        .map_or(2 * ttl, |exp| exp.saturating_sub(iat));
+    RenewalClock::with_times(
+        issued,
+        Duration::from_secs(renew_after),
+        Duration::from_secs(exp_add),
+        Duration::from_secs(expires_after),
+    )
+}
+
+// The renewed token in the Location of a redirect renewal, a redirect that
+// keeps the token of the request is not a renewal
+fn redirect_token(redirects: &[Redirect]) -> Option<String> {
+    let query_token = |url: &Url| {
+        url.query_pairs()
+            .find(|(name, _)| name == TOKEN_QUERY)
+            .map(|(_, token)| token.into_owned())
+    };
+    redirects.iter().rev().find_map(|redirect| {
+        let url = Url::parse(&redirect.url).ok()?;
+        let location = url.join(&redirect.location).ok()?;
+        query_token(&location).filter(|token| query_token(&url).as_ref() != Some(token))
+    })
+}
+
+// The url with the token in the query, replacing a token already there
+fn with_query_token(url: &str, token: &str) -> Result<String, CatTesterError> {
+    let mut url = Url::parse(url).map_err(|e| CatTesterError::Url(format!("{}: {}", url, e)))?;
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != TOKEN_QUERY)
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair(TOKEN_QUERY, token);
+    Ok(url.to_string())
+}
+
+// Relative uris are fetched from the same server as the playlist
+fn is_http_uri(uri: &str) -> bool {
+    match uri.split_once(':') {
+        Some((scheme, _)) if !scheme.contains('/') => {
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        }
+        _ => true,
     }
 }
 
-fn find_line_after_pattern(text: &str, pattern: &str) -> Option<String> {
-    let lines: Vec<&str> = text.lines().collect();
-    for i in 0..lines.len() - 1 {
-        if lines[i].contains(pattern) {
-            return Some(lines[i + 1].trim().to_string());
+// Resolve a segment, init, key or variant uri against the playlist url the
+// way a player does (RFC 3986 section 5). With keep_query the query
+// parameters of the playlist url are added to the resolved url unless it
+// already has a parameter with the same name
+fn resolve_uri(base: &str, uri: &str, keep_query: bool) -> Result<String, CatTesterError> {
+    let invalid = |e| CatTesterError::Url(format!("{} from {}: {}", uri, base, e));
+    let base = Url::parse(base).map_err(invalid)?;
+    let mut resolved = base.join(uri).map_err(invalid)?;
+    if keep_query && base.query().is_some() {
+        let existing: HashSet<String> = resolved
+            .query_pairs()
+            .map(|(name, _)| name.into_owned())
+            .collect();
+        let inherited: Vec<(String, String)> = base
+            .query_pairs()
+            .filter(|(name, _)| !existing.contains(name.as_ref()))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if !inherited.is_empty() {
+            resolved.query_pairs_mut().extend_pairs(inherited);
         }
     }
-    None
+    Ok(resolved.to_string())
+}
+
+// Something a VariantSelector can pick, HLS variants and DASH representations
+trait Selectable {
+    fn bandwidth(&self) -> u64;
+    fn matches(&self, name: &str) -> bool;
+}
+
+impl Selectable for Variant {
+    fn bandwidth(&self) -> u64 {
+        self.bandwidth
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.uri.contains(name) || self.resolution.as_deref().is_some_and(|r| r.contains(name))
+    }
+}
+
+impl Selectable for &Representation {
+    fn bandwidth(&self) -> u64 {
+        self.bandwidth
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.id.contains(name) || self.resolution.as_deref().is_some_and(|r| r.contains(name))
+    }
+}
+
+fn select_variant<'a, T: Selectable>(
+    variants: &'a [T],
+    selector: &VariantSelector,
+) -> Option<&'a T> {
+    match selector {
+        VariantSelector::Highest => variants.iter().max_by_key(|v| v.bandwidth()),
+        VariantSelector::Lowest => variants.iter().min_by_key(|v| v.bandwidth()),
+        VariantSelector::Bandwidth(bandwidth) => variants
+            .iter()
+            .min_by_key(|v| v.bandwidth().abs_diff(*bandwidth)),
+        VariantSelector::Name(name) => variants.iter().find(|v| v.matches(name)),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use common_access_token::current_timestamp;
 
     use httpmock::Method::GET;
-    use httpmock::MockServer;
+    use httpmock::{MockServer, Regex};
+
+    // A token like the one a CDN returns when it renews a token from create_token
+    fn renewed_token(key_hex: &str, issuer: &str, exp_add: u64) -> String {
+        use common_access_token::{
+            Algorithm, KeyId, RegisteredClaims, TokenBuilder, cat_keys, catr,
+        };
+        let now = current_timestamp();
+        let token = TokenBuilder::new()
+            .algorithm(Algorithm::HmacSha256)
+            .unprotected_key_id(KeyId::string("Symmetric256"))
+            .registered_claims(
+                RegisteredClaims::new()
+                    .with_issuer(issuer)
+                    .with_subject("user_id:asset_id:session_id")
+                    .with_issued_at(now)
+                    .with_expiration(now + exp_add),
+            )
+            .custom_cbor(
+                cat_keys::CATR,
+                catr::create(catr::header_renewal(
+                    exp_add as i64,
+                    None,
+                    Some(TOKEN_NAME),
+                    None,
+                )),
+            )
+            .sign(&hex::decode(key_hex).unwrap())
+            .unwrap();
+        URL_SAFE_NO_PAD.encode(token.to_bytes().unwrap())
+    }
 
     #[tokio::test]
     async fn test_run_with_cat_in_header() {
@@ -230,7 +1242,8 @@ mod test {
             "issuer",
             1,
             0,
-        );
+        )
+        .unwrap();
 
         let result = runner.run().await;
         if result.is_err() {
@@ -242,6 +1255,117 @@ mod test {
         segment_mock.assert();
     }
 
+    #[tokio::test]
+    async fn test_run_reports_failed_status_and_renewal() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n#EXTINF:10,\nsegment.ts\n#EXTINF:10,\nforbidden.ts";
+        server.mock(|when, then| {
+            when.method(GET).path("/playlist.m3u8");
+            then.status(200)
+                .header("content-length", body.len().to_string())
+                .body(body);
+        });
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let body = "segment content";
+        server.mock(|when, then| {
+            when.method(GET).path("/segment.ts");
+            then.status(200)
+                .header("content-length", body.len().to_string())
+                .header(
+                    "CTA-Common-Access-Token",
+                    renewed_token(key_hex, "issuer", 3600),
+                )
+                .body(body);
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/forbidden.ts");
+            then.status(403)
+                .header("content-length", "0")
+                .header("CTA-Common-Access-Token", "garbage");
+        });
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/playlist.m3u8", server.base_url()),
+            3600,
+            TokenType::Header,
+            "issuer",
+            2,
+            0,
+        )
+        .unwrap()
+        .with_mode(Mode::Vod)
+        .with_speed(0.0);
+
+        let report = runner.run().await.unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.outcomes.len(), 3);
+        assert!(report.outcomes[0].passed());
+        assert!(report.outcomes[1].passed());
+        assert!(report.outcomes[1].renewed);
+        assert_eq!(report.outcomes[2].status, 403);
+        assert!(matches!(
+            report.outcomes[2].failures.as_slice(),
+            [Failure::Status(403), Failure::InvalidRenewal(_)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_checks_expectations() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n#EXTINF:10,\nsegment.ts";
+        server.mock(|when, then| {
+            when.method(GET).path("/playlist.m3u8");
+            then.status(200)
+                .header("content-length", body.len().to_string())
+                .body(body);
+        });
+        let segment_mock = server.mock(|when, then| {
+            when.method(GET).path("/segment.ts");
+            then.status(401).header("content-length", "0");
+        });
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let worker = |expectations: Expectations| {
+            Worker::new(
+                key_hex,
+                &format!("{}/playlist.m3u8", server.base_url()),
+                3600,
+                TokenType::Header,
+                "issuer",
+                2,
+                0,
+            )
+            .unwrap()
+            .with_expectations(expectations)
+        };
+
+        // request 1 is the playlist
+        let expected_401 = Expectations::default().merge(
+            vec!["2=401".parse().unwrap(), "3=401".parse().unwrap()],
+            None,
+        );
+        assert!(worker(expected_401).run().await.unwrap().passed());
+
+        let expected_renewal = Expectations::default().merge(
+            vec!["2=401".parse().unwrap(), "3=200".parse().unwrap()],
+            Some(3),
+        );
+        let report = worker(expected_renewal).run().await.unwrap();
+        assert!(report.outcomes[1].passed());
+        assert_eq!(
+            report.outcomes[2].failures,
+            vec![
+                Failure::UnexpectedStatus {
+                    expected: 200,
+                    actual: 401
+                },
+                Failure::NoRenewalBy(3)
+            ]
+        );
+        segment_mock.assert_hits(4);
+    }
+
     #[tokio::test]
     async fn test_run_with_cat_in_cookie() {
         let server = MockServer::start();
@@ -275,7 +1399,8 @@ mod test {
             "issuer",
             1,
             0,
-        );
+        )
+        .unwrap();
         let result = runner.run().await;
         if result.is_err() {
             eprintln!("error {:?}", result);
@@ -325,7 +1450,8 @@ mod test {
             "issuer",
             1,
             0,
-        );
+        )
+        .unwrap();
 
         let result = runner.run().await;
         if result.is_err() {
@@ -337,6 +1463,517 @@ mod test {
         segment_mock.assert();
     }
 
+    #[tokio::test]
+    async fn test_run_with_cat_in_query_needs_a_cookie() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n#EXTINF:10,\nsegment.ts";
+        let playlist_mock = server.mock(|when, then| {
+            when.method(GET).path("/playlist.m3u8");
+            then.status(200).body(body);
+        });
+        let runner = Worker::new(
+            "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388",
+            &format!("{}/playlist.m3u8", server.base_url()),
+            3600,
+            TokenType::CookieAsQuery,
+            "issuer",
+            1,
+            0,
+        )
+        .unwrap();
+
+        // without the cookie the segments have no token
+        let result = runner.run().await;
+        assert!(matches!(result, Err(CatTesterError::MissingHeader(_))));
+        playlist_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_run_preflight_has_query_token_and_no_range() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n\
+            #EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"\n\
+            #EXTINF:10,\n#EXT-X-BYTERANGE:1000@720\nmain.mp4\n";
+        let playlist_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/playlist.m3u8")
+                .query_param_exists("CAT");
+            then.status(200).body(body);
+        });
+        let preflight_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::OPTIONS)
+                .path("/main.mp4")
+                .query_param_exists("CAT")
+                .header("origin", PREFLIGHT_ORIGIN)
+                .header("access-control-request-method", "GET")
+                .matches(|request| {
+                    request.headers.iter().flatten().all(|(name, _)| {
+                        !name.eq_ignore_ascii_case("range")
+                            && !name.eq_ignore_ascii_case(TOKEN_NAME)
+                            && !name.eq_ignore_ascii_case("access-control-request-headers")
+                    })
+                });
+            then.status(204);
+        });
+        let runner = Worker::new(
+            "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388",
+            &format!("{}/playlist.m3u8", server.base_url()),
+            3600,
+            TokenType::Query,
+            "issuer",
+            1,
+            0,
+        )
+        .unwrap()
+        .with_method(RequestMethod::Options);
+
+        let report = runner.run().await.unwrap();
+        assert!(report.passed());
+        playlist_mock.assert();
+        // the init segment and the segment
+        preflight_mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_run_follows_master_playlist() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\n\
+            low/playlist.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\"\n\
+            high/playlist.m3u8\n";
+        let master_mock = server.mock(|when, then| {
+            when.method(GET).path("/master.m3u8");
+            then.status(200).body(body);
+        });
+        let body = "#EXTM3U\n#EXTINF:10,\nsegment.ts";
+        let media_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/high/playlist.m3u8")
+                .header_exists("CTA-Common-Access-Token");
+            then.status(200).body(body);
+        });
+        let body = "segment content";
+        let segment_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/high/segment.ts")
+                .header_exists("CTA-Common-Access-Token");
+            then.status(200)
+                .header("content-length", body.len().to_string())
+                .body(body);
+        });
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/master.m3u8", server.base_url()),
+            3600,
+            TokenType::Header,
+            "issuer",
+            1,
+            0,
+        )
+        .unwrap()
+        .with_variant(VariantSelector::Highest);
+
+        let result = runner.run().await;
+        if result.is_err() {
+            eprintln!("error {:?}", result);
+        }
+        assert!(result.is_ok());
+
+        master_mock.assert();
+        media_mock.assert();
+        segment_mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_run_with_dash_manifest() {
+        let server = MockServer::start();
+
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT8S">
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate timescale="1000" duration="4000" initialization="$RepresentationID$.dash" media="$RepresentationID$-$Number$.dash" />
+      <Representation id="video=799968" bandwidth="799968" />
+      <Representation id="video=5000000" bandwidth="5000000" />
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd_mock = server.mock(|when, then| {
+            when.method(GET).path("/asset/5245.isml/.mpd");
+            then.status(200)
+                .header("content-type", "application/dash+xml")
+                .body(body);
+        });
+        let body = "segment content";
+        let segment_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/asset/5245.isml/video=799968-1.dash")
+                .cookie_exists("CTA-Common-Access-Token");
+            then.status(200)
+                .header("content-length", body.len().to_string())
+                .body(body);
+        });
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/asset/5245.isml/.mpd", server.base_url()),
+            3600,
+            TokenType::Cookie,
+            "issuer",
+            2,
+            0,
+        )
+        .unwrap()
+        .with_variant(VariantSelector::Lowest);
+
+        let result = runner.run().await;
+        if result.is_err() {
+            eprintln!("error {:?}", result);
+        }
+        assert!(result.is_ok());
+
+        mpd_mock.assert();
+        segment_mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_run_live_fetches_new_segments_once() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n\
+            #EXT-X-TARGETDURATION:0\n\
+            #EXT-X-MEDIA-SEQUENCE:100\n\
+            #EXTINF:4,\nsegment-100.ts\n\
+            #EXTINF:4,\nsegment-101.ts\n\
+            #EXTINF:4,\nsegment-102.ts\n\
+            #EXTINF:4,\nsegment-103.ts\n\
+            #EXTINF:4,\nsegment-104.ts\n";
+        let playlist_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/live.m3u8")
+                .header_exists("CTA-Common-Access-Token");
+            then.status(200).body(body);
+        });
+        let segment_mock = |path: &'static str| {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(path)
+                    .header_exists("CTA-Common-Access-Token");
+                then.status(200)
+                    .header("content-length", "15")
+                    .body("segment content");
+            })
+        };
+        let old_segment = segment_mock("/segment-101.ts");
+        let live_segments = [
+            segment_mock("/segment-102.ts"),
+            segment_mock("/segment-103.ts"),
+            segment_mock("/segment-104.ts"),
+        ];
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/live.m3u8", server.base_url()),
+            3600,
+            TokenType::Header,
+            "issuer",
+            10,
+            0,
+        )
+        .unwrap()
+        .with_mode(Mode::Live)
+        .with_duration(Some(Duration::from_millis(500)))
+        .with_clock(Clock::manual(Duration::from_secs(1_700_000_000)))
+        .unwrap();
+
+        let result = runner.run().await;
+        if result.is_err() {
+            eprintln!("error {:?}", result);
+        }
+        assert!(result.is_ok());
+
+        // the playlist does not move so it is reloaded without fetching anything new
+        assert!(playlist_mock.hits() > 1);
+        old_segment.assert_hits(0);
+        for segment in live_segments {
+            segment.assert_hits(1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_live_stops_at_segment_count() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n\
+            #EXT-X-TARGETDURATION:4\n\
+            #EXT-X-MEDIA-SEQUENCE:7\n\
+            #EXTINF:4,\nsegment-7.ts\n\
+            #EXTINF:4,\nsegment-8.ts\n\
+            #EXTINF:4,\nsegment-9.ts\n";
+        let playlist_mock = server.mock(|when, then| {
+            when.method(GET).path("/live.m3u8");
+            then.status(200).body(body);
+        });
+        let segment_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path_matches(Regex::new("/segment-[78].ts").unwrap());
+            then.status(200)
+                .header("content-length", "15")
+                .body("segment content");
+        });
+        let last_segment = server.mock(|when, then| {
+            when.method(GET).path("/segment-9.ts");
+            then.status(200)
+                .header("content-length", "15")
+                .body("segment content");
+        });
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/live.m3u8", server.base_url()),
+            3600,
+            TokenType::Cookie,
+            "issuer",
+            2,
+            0,
+        )
+        .unwrap()
+        .with_mode(Mode::Live);
+
+        let result = runner.run().await;
+        assert!(result.is_ok());
+
+        playlist_mock.assert_hits(1);
+        segment_mock.assert_hits(2);
+        last_segment.assert_hits(0);
+    }
+
+    #[tokio::test]
+    async fn test_run_live_records_rejected_playlist_reload() {
+        let server = MockServer::start();
+
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let renewed = renewed_token(key_hex, "issuer", 3600);
+        // the reload is sent with the renewed token, the first mock that
+        // matches a request answers it
+        let reload_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/live.m3u8")
+                .header("CTA-Common-Access-Token", &renewed);
+            then.status(401);
+        });
+        let body = "#EXTM3U
+#EXT-X-TARGETDURATION:0
+#EXTINF:4,
+segment.ts
+";
+        server.mock(|when, then| {
+            when.method(GET).path("/live.m3u8");
+            then.status(200).body(body);
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/segment.ts");
+            then.status(200)
+                .header("content-length", "15")
+                .header("CTA-Common-Access-Token", &renewed)
+                .body("segment content");
+        });
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/live.m3u8", server.base_url()),
+            3600,
+            TokenType::Header,
+            "issuer",
+            10,
+            0,
+        )
+        .unwrap()
+        .with_mode(Mode::Live)
+        .with_expectations(Expectations::default().merge(vec!["3=401".parse().unwrap()], None));
+
+        let report = runner.run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.outcomes.len(), 3);
+        assert_eq!(report.outcomes[2].status, 401);
+        assert!(report.outcomes[2].url.ends_with("/live.m3u8"));
+        reload_mock.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn test_run_vod_fetches_init_and_every_segment() {
+        let server = MockServer::start();
+
+        let body = "#EXTM3U\n\
+            #EXT-X-PLAYLIST-TYPE:VOD\n\
+            #EXT-X-TARGETDURATION:4\n\
+            #EXT-X-MAP:URI=\"hls/5245-video=5000000.m4s\"\n\
+            #EXTINF:0.1,\nhls/5245-video=5000000-1.m4s\n\
+            #EXTINF:0.1,\nhls/5245-video=5000000-2.m4s\n\
+            #EXTINF:0.1,\nhls/5245-video=5000000-3.m4s\n\
+            #EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"\n\
+            #EXTINF:0.1,\n#EXT-X-BYTERANGE:1000@720\nmain.mp4\n\
+            #EXT-X-ENDLIST\n";
+        let playlist_mock = server.mock(|when, then| {
+            when.method(GET).path("/asset/vod.m3u8");
+            then.status(200).body(body);
+        });
+        let segment_mock = |path: &'static str| {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(path)
+                    .header_exists("CTA-Common-Access-Token");
+                then.status(200)
+                    .header("content-length", "15")
+                    .body("segment content");
+            })
+        };
+        let mocks = [
+            segment_mock("/asset/hls/5245-video=5000000.m4s"),
+            segment_mock("/asset/hls/5245-video=5000000-1.m4s"),
+            segment_mock("/asset/hls/5245-video=5000000-2.m4s"),
+            segment_mock("/asset/hls/5245-video=5000000-3.m4s"),
+        ];
+        let init_range = server.mock(|when, then| {
+            when.method(GET)
+                .path("/asset/main.mp4")
+                .header("range", "bytes=0-719");
+            then.status(206).header("content-length", "4").body("init");
+        });
+        let segment_range = server.mock(|when, then| {
+            when.method(GET)
+                .path("/asset/main.mp4")
+                .header("range", "bytes=720-1719");
+            then.status(206).header("content-length", "5").body("media");
+        });
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let clock = Clock::manual(Duration::from_secs(1_700_000_000));
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/asset/vod.m3u8", server.base_url()),
+            3600,
+            TokenType::Header,
+            "issuer",
+            1,
+            0,
+        )
+        .unwrap()
+        .with_mode(Mode::Vod)
+        .with_speed(2.0)
+        .with_clock(clock.clone())
+        .unwrap();
+
+        let started = clock.now();
+        let result = runner.run().await;
+        if result.is_err() {
+            eprintln!("error {:?}", result);
+        }
+        assert!(result.is_ok());
+        // 0.3s of media before the last segment, at double speed
+        assert_eq!(clock.elapsed(started), Duration::from_millis(150));
+
+        playlist_mock.assert();
+        for mock in mocks {
+            mock.assert();
+        }
+        init_range.assert();
+        segment_range.assert();
+    }
+
+    #[tokio::test]
+    async fn test_run_fetches_init_and_http_keys_with_token() {
+        let server = MockServer::start();
+
+        let body = r#"#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXT-X-MAP:URI="hls/5245-video=5000000.m4s"
+#EXT-X-KEY:METHOD=SAMPLE-AES,URI="data:text/plain;base64,AAAAdXBzc2gAAAAA",KEYFORMAT="urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"
+#EXT-X-KEY:METHOD=SAMPLE-AES,URI="skd://drmtoday?assetId=media-20465721",KEYFORMAT="com.apple.streamingkeydelivery"
+#EXT-X-KEY:METHOD=AES-128,URI="keys/key1"
+#EXTINF:3.84, no desc
+hls/5245-video=5000000-455767831.m4s
+"#;
+        let playlist_mock = server.mock(|when, then| {
+            when.method(GET).path("/asset/5245.m3u8");
+            then.status(200).body(body);
+        });
+        let mock = |path: &'static str| {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(path)
+                    .cookie_exists("CTA-Common-Access-Token");
+                then.status(200)
+                    .header("content-length", "15")
+                    .body("segment content");
+            })
+        };
+        let init_mock = mock("/asset/hls/5245-video=5000000.m4s");
+        let key_mock = mock("/asset/keys/key1");
+        let segment_mock = mock("/asset/hls/5245-video=5000000-455767831.m4s");
+        let key_hex = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let runner = Worker::new(
+            key_hex,
+            &format!("{}/asset/5245.m3u8", server.base_url()),
+            3600,
+            TokenType::Cookie,
+            "issuer",
+            3,
+            0,
+        )
+        .unwrap();
+
+        let result = runner.run().await;
+        if result.is_err() {
+            eprintln!("error {:?}", result);
+        }
+        assert!(result.is_ok());
+
+        playlist_mock.assert();
+        init_mock.assert_hits(1);
+        key_mock.assert_hits(1);
+        segment_mock.assert_hits(3);
+    }
+
+    #[test]
+    fn key_uris_to_fetch() {
+        assert!(is_http_uri("https://keys.example.com/key1"));
+        assert!(is_http_uri("HTTP://keys.example.com/key1"));
+        assert!(is_http_uri("keys/key1"));
+        assert!(is_http_uri("/keys/key1?a=b:c"));
+        assert!(!is_http_uri("skd://drmtoday?assetId=media-20465721"));
+        assert!(!is_http_uri("data:text/plain;base64,AAAA"));
+    }
+
+    #[test]
+    fn select_variant_from_master_playlist() {
+        let variant = |bandwidth: u64, resolution: &str, uri: &str| Variant {
+            bandwidth,
+            resolution: Some(resolution.to_string()),
+            uri: uri.to_string(),
+            ..Default::default()
+        };
+        let variants = vec![
+            variant(2500000, "1280x720", "5245-video=2499968.m3u8"),
+            variant(800000, "640x360", "5245-video=799968.m3u8"),
+            variant(5000000, "1920x1080", "5245-video=5000000.m3u8"),
+        ];
+
+        let select = |s: &str| {
+            let selector = s.parse::<VariantSelector>().unwrap();
+            select_variant(&variants, &selector).map(|v| v.uri.as_str())
+        };
+        assert_eq!(select("highest"), Some("5245-video=5000000.m3u8"));
+        assert_eq!(select("lowest"), Some("5245-video=799968.m3u8"));
+        assert_eq!(select("2000000"), Some("5245-video=2499968.m3u8"));
+        assert_eq!(select("1280x720"), Some("5245-video=2499968.m3u8"));
+        assert_eq!(select("video=799968"), Some("5245-video=799968.m3u8"));
+        assert_eq!(select("unknown"), None);
+    }
+
     #[test]
     fn get_cookie_domain_from_host() {
         let host = "www.host1.example.com";
@@ -352,62 +1989,125 @@ mod test {
     }
 
     #[test]
-    fn replace_last_segment() {
-        let url = "https://my.test.domain.com/first/second/last.ism";
-        let last_path = "replaced.ism";
-        let result = replace_last_path_segment(&url, &last_path);
-        assert_eq!(
-            result,
-            "https://my.test.domain.com/first/second/replaced.ism"
-        );
+    fn resolve_relative_uris() {
+        // RFC 3986 section 5.4.1, normal examples
+        let base = "http://a/b/c/d;p?q";
+        let rfc_cases = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g/"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g#s", "http://a/b/c/g#s"),
+            ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"),
+            ("g;x", "http://a/b/c/g;x"),
+            ("g;x?y#s", "http://a/b/c/g;x?y#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../", "http://a/"),
+            ("../../g", "http://a/g"),
+            // abnormal examples
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+        ];
+        for (uri, expected) in rfc_cases {
+            assert_eq!(resolve_uri(base, uri, false).unwrap(), expected, "{}", uri);
+        }
+
+        let manifest = "https://my.test.domain.com/first/second/last.ism/index.m3u8?session=1&a=b";
+        let manifest_cases = [
+            (
+                "replaced.m4s",
+                false,
+                "https://my.test.domain.com/first/second/last.ism/replaced.m4s",
+            ),
+            (
+                "httpseg/1.ts",
+                false,
+                "https://my.test.domain.com/first/second/last.ism/httpseg/1.ts",
+            ),
+            (
+                "../other.ism/hls/1.m4s",
+                false,
+                "https://my.test.domain.com/first/second/other.ism/hls/1.m4s",
+            ),
+            (
+                "/hls/5245-video=5000000.m4s",
+                false,
+                "https://my.test.domain.com/hls/5245-video=5000000.m4s",
+            ),
+            (
+                "//cdn.example.com/hls/1.ts",
+                false,
+                "https://cdn.example.com/hls/1.ts",
+            ),
+            (
+                "http://other.example.com/1.ts",
+                false,
+                "http://other.example.com/1.ts",
+            ),
+            (
+                "hls/1.m4s",
+                true,
+                "https://my.test.domain.com/first/second/last.ism/hls/1.m4s?session=1&a=b",
+            ),
+            (
+                "hls/1.m4s?session=2",
+                true,
+                "https://my.test.domain.com/first/second/last.ism/hls/1.m4s?session=2&a=b",
+            ),
+        ];
+        for (uri, keep_query, expected) in manifest_cases {
+            assert_eq!(
+                resolve_uri(manifest, uri, keep_query).unwrap(),
+                expected,
+                "{}",
+                uri
+            );
+        }
     }
 
     #[test]
-    fn parse_m3u8_response() {
-        let body_two = r#"
-        #EXTM3U
-        #EXTM3U
-        #EXT-X-VERSION:6
-        ## Created with Unified Streaming Platform  (version=1.13.0-29687)
-        #EXT-X-MEDIA-SEQUENCE:455767831
-        #EXT-X-INDEPENDENT-SEGMENTS
-        #EXT-X-TARGETDURATION:6
-        #USP-X-TIMESTAMP-MAP:MPEGTS=8322344688,LOCAL=2025-06-17T08:21:08.040000Z
-        #EXT-X-MAP:URI="hls/20465721-video=5000000.m4s"
-        #EXT-X-PROGRAM-DATE-TIME:2025-06-17T08:21:08.040000Z
-        #EXT-X-KEY:METHOD=SAMPLE-AES,KEYID=0x706fe4f5a1fc3ad2af49a6698b822bad,URI="data:text/plain;base64,AAAAdXBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAAFUSEHBv5PWh/DrSr0mmaYuCK60aCGNhc3RsYWJzIihleUpoYzNObGRFbGtJam9pZEhadFpXUnBZUzB5TURRMk5UY3lNU0o5MgdkZWZhdWx0SPPGiZsG",KEYFORMAT="urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed",KEYFORMATVERSIONS="1"
-        #EXT-X-KEY:METHOD=SAMPLE-AES,URI="data:text/plain;charset=UTF-16;base64,KgMAAAEAAQAgAzwAVwBSAE0ASABFAEEARABFAFIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMwAuADAALgAwACIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAVgBBAEwAVQBFAD0AIgA5AGUAUgB2AGMAUAB5AGgAMABqAHEAdgBTAGEAWgBwAGkANABJAHIAcgBRAD0APQAiACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAEIAQwAiACAALwA+ADwALwBLAEkARABTAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwATABBAF8AVQBSAEwAPgBoAHQAdABwAHMAOgAvAC8AbABpAGMALgBkAHIAbQB0AG8AZABhAHkALgBjAG8AbQAvAGwAaQBjAGUAbgBzAGUALQBwAHIAbwB4AHkALQBoAGUAYQBkAGUAcgBhAHUAdABoAC8AZAByAG0AdABvAGQAYQB5AC8AUgBpAGcAaAB0AHMATQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPAAvAEwAQQBfAFUAUgBMAD4APABMAFUASQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHAAbABhAHkAcgBlAGEAZAB5AC0AdQBpAC4AZQB4AGEAbQBwAGwAZQAuAGMAbwBtADwALwBMAFUASQBfAFUAUgBMAD4APABEAEUAQwBSAFkAUABUAE8AUgBTAEUAVABVAFAAPgBPAE4ARABFAE0AQQBOAEQAPAAvAEQARQBDAFIAWQBQAFQATwBSAFMARQBUAFUAUAA+ADwALwBEAEEAVABBAD4APAAvAFcAUgBNAEgARQBBAEQARQBSAD4A",KEYFORMAT="com.microsoft.playready",KEYFORMATVERSIONS="1"
-        #EXT-X-KEY:METHOD=SAMPLE-AES,URI="skd://drmtoday?assetId=tvmedia-20465721&variantId&keyId=706fe4f5a1fc3ad2af49a6698b822bad",KEYFORMAT="com.apple.streamingkeydelivery",KEYFORMATVERSIONS="1"
-        #EXT-X-KEY:METHOD=SAMPLE-AES,KEYID=0x706fe4f5a1fc3ad2af49a6698b822bad,URI="data:text/plain;base64,AAAAn3Bzc2gAAAAAPV5tNZuaQei4Q908bnLELAAAAH97InZlcnNpb24iOiJWMS4wIiwia2lkcyI6WyJjRy9rOWFIOE90S3ZTYVpwaTRJcnJRPT0iXSwiY29udGVudElEIjoiZXlKaGMzTmxkRWxrSWpvaWRIWnRaV1JwWVMweU1EUTJOVGN5TVNKOSIsImVuc2NoZW1hIjoiY2JjcyJ9",IV=0xE105A618D09DC0CCFFCDBCCA711E6BD0,KEYFORMAT="urn:uuid:3d5e6d35-9b9a-41e8-b843-dd3c6e72c42c",KEYFORMATVERSIONS="1"
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767831.m4s
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767832.m4s
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767833.m4s
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767834.m4s#EXT-X-VERSION:6
-        ## Created with Unified Streaming Platform  (version=1.13.0-29687)
-        #EXT-X-MEDIA-SEQUENCE:455767831
-        #EXT-X-INDEPENDENT-SEGMENTS
-        #EXT-X-TARGETDURATION:6
-        #USP-X-TIMESTAMP-MAP:MPEGTS=8322344688,LOCAL=2025-06-17T08:21:08.040000Z
-        #EXT-X-MAP:URI="hls/20465721-video=5000000.m4s"
-        #EXT-X-PROGRAM-DATE-TIME:2025-06-17T08:21:08.040000Z
-        #EXT-X-KEY:METHOD=SAMPLE-AES,KEYID=0x706fe4f5a1fc3ad2af49a6698b822bad,URI="data:text/plain;base64,AAAAdXBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAAFUSEHBv5PWh/DrSr0mmaYuCK60aCGNhc3RsYWJzIihleUpoYzNObGRFbGtJam9pZEhadFpXUnBZUzB5TURRMk5UY3lNU0o5MgdkZWZhdWx0SPPGiZsG",KEYFORMAT="urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed",KEYFORMATVERSIONS="1"
-        #EXT-X-KEY:METHOD=SAMPLE-AES,URI="data:text/plain;charset=UTF-16;base64,KgMAAAEAAQAgAzwAVwBSAE0ASABFAEEARABFAFIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMwAuADAALgAwACIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAVgBBAEwAVQBFAD0AIgA5AGUAUgB2AGMAUAB5AGgAMABqAHEAdgBTAGEAWgBwAGkANABJAHIAcgBRAD0APQAiACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAEIAQwAiACAALwA+ADwALwBLAEkARABTAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwATABBAF8AVQBSAEwAPgBoAHQAdABwAHMAOgAvAC8AbABpAGMALgBkAHIAbQB0AG8AZABhAHkALgBjAG8AbQAvAGwAaQBjAGUAbgBzAGUALQBwAHIAbwB4AHkALQBoAGUAYQBkAGUAcgBhAHUAdABoAC8AZAByAG0AdABvAGQAYQB5AC8AUgBpAGcAaAB0AHMATQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPAAvAEwAQQBfAFUAUgBMAD4APABMAFUASQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHAAbABhAHkAcgBlAGEAZAB5AC0AdQBpAC4AZQB4AGEAbQBwAGwAZQAuAGMAbwBtADwALwBMAFUASQBfAFUAUgBMAD4APABEAEUAQwBSAFkAUABUAE8AUgBTAEUAVABVAFAAPgBPAE4ARABFAE0AQQBOAEQAPAAvAEQARQBDAFIAWQBQAFQATwBSAFMARQBUAFUAUAA+ADwALwBEAEEAVABBAD4APAAvAFcAUgBNAEgARQBBAEQARQBSAD4A",KEYFORMAT="com.microsoft.playready",KEYFORMATVERSIONS="1"
-        #EXT-X-KEY:METHOD=SAMPLE-AES,URI="skd://drmtoday?assetId=tvmedia-20465721&variantId&keyId=706fe4f5a1fc3ad2af49a6698b822bad",KEYFORMAT="com.apple.streamingkeydelivery",KEYFORMATVERSIONS="1"
-        #EXT-X-KEY:METHOD=SAMPLE-AES,KEYID=0x706fe4f5a1fc3ad2af49a6698b822bad,URI="data:text/plain;base64,AAAAn3Bzc2gAAAAAPV5tNZuaQei4Q908bnLELAAAAH97InZlcnNpb24iOiJWMS4wIiwia2lkcyI6WyJjRy9rOWFIOE90S3ZTYVpwaTRJcnJRPT0iXSwiY29udGVudElEIjoiZXlKaGMzTmxkRWxrSWpvaWRIWnRaV1JwWVMweU1EUTJOVGN5TVNKOSIsImVuc2NoZW1hIjoiY2JjcyJ9",IV=0xE105A618D09DC0CCFFCDBCCA711E6BD0,KEYFORMAT="urn:uuid:3d5e6d35-9b9a-41e8-b843-dd3c6e72c42c",KEYFORMATVERSIONS="1"
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767831.m4s
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767832.m4s
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767833.m4s
-        #EXTINF:3.84, no desc
-        hls/20465721-video=5000000-455767834.m4s
-        "#;
-        let res = find_line_after_pattern(body_two, "EXTINF");
-        assert_eq!(res.unwrap(), "hls/20465721-video=5000000-455767831.m4s");
+    fn token_from_redirect_renewal() {
+        let redirect = |url: &str, location: &str| Redirect {
+            status: 302,
+            url: url.to_string(),
+            location: location.to_string(),
+        };
+        assert_eq!(
+            redirect_token(&[redirect(
+                "http://cdn/live/segment_1.ts?a=1",
+                "segment_1.ts?a=1&CAT=new"
+            )]),
+            Some(String::from("new"))
+        );
+        // a redirect that keeps the token is not a renewal
+        assert_eq!(
+            redirect_token(&[redirect(
+                "http://cdn/index.m3u8?CAT=old",
+                "/live/index.m3u8?CAT=old"
+            )]),
+            None
+        );
+        assert_eq!(
+            with_query_token("http://cdn/segment_1.ts?CAT=old&a=1", "new").unwrap(),
+            "http://cdn/segment_1.ts?a=1&CAT=new"
+        );
     }
 }
@@ -0,0 +1,48 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The time tokens are issued and checked at and requests are paced by, the
+/// system clock by default. A manual clock only moves when something sleeps
+/// on it, a worker and a mock CDN that share one run without waiting
+#[derive(Clone, Debug, Default)]
+pub struct Clock {
+    manual: Option<Arc<Mutex<Duration>>>,
+}
+
+impl Clock {
+    /// A manual clock that starts at `start` since the epoch
+    #[cfg(test)]
+    pub fn manual(start: Duration) -> Self {
+        Self {
+            manual: Some(Arc::new(Mutex::new(start))),
+        }
+    }
+
+    /// Time since the epoch
+    pub fn now(&self) -> Duration {
+        match &self.manual {
+            Some(now) => *now.lock().unwrap_or_else(|e| e.into_inner()),
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Seconds since the epoch, the time in a token
+    pub fn timestamp(&self) -> u64 {
+        self.now().as_secs()
+    }
+
+    /// Time since `earlier`, zero if the clock has not moved since
+    pub fn elapsed(&self, earlier: Duration) -> Duration {
+        self.now().saturating_sub(earlier)
+    }
+
+    /// Wait, a manual clock moves forward instead
+    pub async fn sleep(&self, duration: Duration) {
+        match &self.manual {
+            Some(now) => *now.lock().unwrap_or_else(|e| e.into_inner()) += duration,
+            None => tokio::time::sleep(duration).await,
+        }
+    }
+}
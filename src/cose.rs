@@ -0,0 +1,640 @@
+use crate::error::CatTesterError;
+use crate::token::Key;
+use common_access_token::{CborValue, Token, cat_keys};
+use ed25519_dalek::{Signer, Verifier};
+use hmac::{Hmac, Mac};
+use minicbor::data::Type;
+use minicbor::{Decoder, Encoder, decode};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+
+/// CWT tag 61
+const CWT_TAG: [u8; 2] = [0xd8, 0x3d];
+/// COSE_Mac0 tag 17
+const MAC0_TAG: u8 = 0xd1;
+/// COSE_Sign1 tag 18
+const SIGN1_TAG: u8 = 0xd2;
+/// COSE header parameter with the algorithm
+pub const ALG: i32 = 1;
+/// COSE header parameter with the critical header parameters
+pub const CRIT: i32 = 2;
+/// COSE header parameter with the key id
+pub const KID: i32 = 4;
+/// CWT claims with the expiration time and the not before time, RFC 8392
+pub const EXP: i32 = 4;
+pub const NBF: i32 = 5;
+/// COSE algorithms of the keys, RFC 9053
+const HMAC_256_256: i64 = 5;
+const ES256: i64 = -7;
+const EDDSA: i64 = -8;
+/// RFC 9164 tags of an IPv4 and an IPv6 address or prefix
+pub const IPV4_TAG: u64 = 52;
+pub const IPV6_TAG: u64 = 54;
+/// Claims the token library can not encode, maps with text keys and arrays
+/// of tagged networks. They are handed to it as a byte string with the
+/// encoded claim and put in the payload as they are when the token is sealed
+const RAW_CLAIMS: [i64; 3] = [
+    cat_keys::CATNIP as i64,
+    cat_keys::CATH as i64,
+    cat_keys::CATIF as i64,
+];
+
+/// The parts of a COSE_Mac0 or COSE_Sign1 structure, RFC 9052 sections 4.2
+/// and 6.2
+struct Message {
+    /// COSE_Mac0 or COSE_Sign1 by the COSE tag, for errors
+    structure: &'static str,
+    protected: Vec<u8>,
+    /// The encoded unprotected header map
+    unprotected: Vec<u8>,
+    payload: Vec<u8>,
+    /// The MAC tag or the signature
+    tag: Vec<u8>,
+}
+
+impl Message {
+    fn from_bytes(token: &[u8]) -> Result<Self, CatTesterError> {
+        let mut d = Decoder::new(token);
+        // the COSE tag tells the structures apart
+        let mut structure = "COSE message";
+        while d.datatype().map_err(|e| invalid(structure, e))? == Type::Tag {
+            structure = match token.get(d.position()) {
+                Some(&MAC0_TAG) => "COSE_Mac0",
+                Some(&SIGN1_TAG) => "COSE_Sign1",
+                _ => structure,
+            };
+            d.tag().map_err(|e| invalid(structure, e))?;
+        }
+        if d.array().map_err(|e| invalid(structure, e))? != Some(4) {
+            return Err(invalid(structure, "not an array of four"));
+        }
+        let protected = d.bytes().map_err(|e| invalid(structure, e))?.to_vec();
+        let unprotected = encoded(&mut d).map_err(|e| invalid(structure, e))?.to_vec();
+        let payload = d.bytes().map_err(|e| invalid(structure, e))?.to_vec();
+        let tag = d.bytes().map_err(|e| invalid(structure, e))?.to_vec();
+        Ok(Self {
+            structure,
+            protected,
+            unprotected,
+            payload,
+            tag,
+        })
+    }
+
+    // The token as a CWT with the COSE tag
+    fn to_bytes(&self, cose_tag: u8) -> Vec<u8> {
+        let mut out = CWT_TAG.to_vec();
+        out.push(cose_tag);
+        out.extend(cbor(|e| {
+            e.array(4)?.bytes(&self.protected)?;
+            e.writer_mut().extend_from_slice(&self.unprotected);
+            e.bytes(&self.payload)?.bytes(&self.tag)?;
+            Ok(())
+        }));
+        out
+    }
+
+    fn alg(&self) -> Result<Option<i64>, CatTesterError> {
+        if self.protected.is_empty() {
+            return Ok(None);
+        }
+        match entries(&self.protected)
+            .map_err(|e| invalid(self.structure, e))?
+            .into_iter()
+            .find(|(label, _)| *label == i64::from(ALG))
+            .map(|(_, alg)| value(alg))
+            .transpose()
+            .map_err(|e| invalid(self.structure, e))?
+        {
+            Some(CborValue::Integer(alg)) => Ok(Some(alg)),
+            Some(_) => Err(invalid(self.structure, "alg is not an integer")),
+            None => Ok(None),
+        }
+    }
+
+    // The MAC_structure or Sig_structure with an empty external aad, RFC 9052
+    // sections 4.4 and 6.3
+    fn to_be_signed(&self, context: &str) -> Vec<u8> {
+        cbor(|e| {
+            e.array(4)?
+                .str(context)?
+                .bytes(&self.protected)?
+                .bytes(&[])?
+                .bytes(&self.payload)?;
+            Ok(())
+        })
+    }
+}
+
+fn alg_of(key: &Key) -> i64 {
+    match key {
+        Key::Hmac(_) => HMAC_256_256,
+        Key::Es256(_) => ES256,
+        Key::EdDsa(_) => EDDSA,
+    }
+}
+
+fn hmac(secret: &[u8]) -> Result<Hmac<Sha256>, CatTesterError> {
+    Hmac::<Sha256>::new_from_slice(secret).map_err(|e| CatTesterError::Key(e.to_string()))
+}
+
+/// Put the claims and the crit header the token library can not encode in
+/// a token it made and sign it with the key, a COSE_Mac0 for an HMAC key
+/// and a COSE_Sign1 for a private key
+pub fn seal(token: &[u8], key: &Key, crit: &[i64]) -> Result<Vec<u8>, CatTesterError> {
+    let mut message = Message::from_bytes(token)?;
+    message.protected = protected(&message.protected, alg_of(key), crit)
+        .map_err(|e| invalid(message.structure, e))?;
+    message.payload = payload(&message.payload, true).map_err(|e| invalid(message.structure, e))?;
+    let cose_tag = match key {
+        Key::Hmac(secret) => {
+            let mac = hmac(secret)?.chain_update(message.to_be_signed("MAC0"));
+            message.tag = mac.finalize().into_bytes().to_vec();
+            MAC0_TAG
+        }
+        Key::Es256(key) => {
+            let signature: p256::ecdsa::Signature = key.sign(&message.to_be_signed("Signature1"));
+            message.tag = signature.to_bytes().to_vec();
+            SIGN1_TAG
+        }
+        Key::EdDsa(key) => {
+            message.tag = key.sign(&message.to_be_signed("Signature1")).to_vec();
+            SIGN1_TAG
+        }
+    };
+    Ok(message.to_bytes(cose_tag))
+}
+
+/// Whether a token with these claims has to be sealed for the token library
+/// to encode them
+pub fn has_raw_claims(custom: &BTreeMap<i32, CborValue>) -> bool {
+    custom
+        .keys()
+        .any(|key| RAW_CLAIMS.contains(&i64::from(*key)))
+}
+
+/// Decode a token with the token library, the claims in RAW_CLAIMS are
+/// byte strings with the encoded claim. A COSE_Sign1 is handed to the
+/// library as a COSE_Mac0 with the signature as the tag
+pub fn open(token: &[u8]) -> Result<Token, CatTesterError> {
+    let mut message = Message::from_bytes(token)?;
+    message.payload =
+        payload(&message.payload, false).map_err(|e| invalid(message.structure, e))?;
+    Ok(Token::from_bytes(&message.to_bytes(MAC0_TAG))?)
+}
+
+/// Check the tag or signature over the token as it was received, a decoded
+/// token can not be encoded to the same bytes. A signature is checked with
+/// the public key of the private key
+pub fn verify(token: &[u8], key: &Key) -> Result<(), CatTesterError> {
+    let message = Message::from_bytes(token)?;
+    let alg = message.alg()?;
+    if alg != Some(alg_of(key)) {
+        return Err(CatTesterError::Token(format!(
+            "alg {:?} is not the alg {} of the key",
+            alg,
+            alg_of(key)
+        )));
+    }
+    let valid = match key {
+        Key::Hmac(secret) => hmac(secret)?
+            .chain_update(message.to_be_signed("MAC0"))
+            .verify_slice(&message.tag)
+            .is_ok(),
+        Key::Es256(key) => {
+            p256::ecdsa::Signature::from_slice(&message.tag).is_ok_and(|signature| {
+                key.verifying_key()
+                    .verify(&message.to_be_signed("Signature1"), &signature)
+                    .is_ok()
+            })
+        }
+        Key::EdDsa(key) => {
+            ed25519_dalek::Signature::from_slice(&message.tag).is_ok_and(|signature| {
+                key.verifying_key()
+                    .verify(&message.to_be_signed("Signature1"), &signature)
+                    .is_ok()
+            })
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(CatTesterError::Token(String::from("tag does not match")))
+    }
+}
+
+// The protected header with the algorithm of the key and crit as the critical
+// header parameters, RFC 9052 section 3.1
+fn protected(protected: &[u8], alg: i64, crit: &[i64]) -> Result<Vec<u8>, String> {
+    let headers = match protected {
+        [] => Vec::new(),
+        protected => entries(protected)?,
+    };
+    let headers: Vec<_> = headers
+        .into_iter()
+        .filter(|(label, _)| *label != i64::from(ALG) && *label != i64::from(CRIT))
+        .collect();
+    Ok(cbor(|e| {
+        e.map(headers.len() as u64 + 1 + u64::from(!crit.is_empty()))?;
+        e.i64(ALG.into())?.i64(alg)?;
+        for (label, header) in &headers {
+            e.i64(*label)?;
+            e.writer_mut().extend_from_slice(header);
+        }
+        if !crit.is_empty() {
+            e.i64(CRIT.into())?.array(crit.len() as u64)?;
+            for label in crit {
+                e.i64(*label)?;
+            }
+        }
+        Ok(())
+    }))
+}
+
+// The payload with the claims in RAW_CLAIMS unwrapped from their byte string
+// when sealing and wrapped in one when opening, other claims are copied
+fn payload(payload: &[u8], seal: bool) -> Result<Vec<u8>, String> {
+    let claims = entries(payload)?;
+    Ok(cbor(|e| {
+        e.map(claims.len() as u64)?;
+        for (key, claim) in &claims {
+            e.i64(*key)?;
+            let raw = RAW_CLAIMS.contains(key);
+            match value(claim) {
+                Ok(CborValue::Bytes(inner)) if raw && seal => {
+                    e.writer_mut().extend_from_slice(&inner);
+                }
+                Ok(CborValue::Bytes(_)) => e.writer_mut().extend_from_slice(claim),
+                _ if raw && !seal => {
+                    e.bytes(claim)?;
+                }
+                _ => e.writer_mut().extend_from_slice(claim),
+            }
+        }
+        Ok(())
+    }))
+}
+
+/// Encode a value like the token library does
+pub fn encode(value: &CborValue) -> Vec<u8> {
+    cbor(|e| write_value(e, value))
+}
+
+/// Encode a map with text keys from entries with encoded values
+pub fn encode_text_map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    cbor(|e| {
+        e.map(entries.len() as u64)?;
+        for (key, value) in entries {
+            e.str(key)?;
+            e.writer_mut().extend_from_slice(value);
+        }
+        Ok(())
+    })
+}
+
+/// Encode a map with integer keys from entries with encoded values
+pub fn encode_map(entries: &[(i64, Vec<u8>)]) -> Vec<u8> {
+    cbor(|e| {
+        e.map(entries.len() as u64)?;
+        for (key, value) in entries {
+            e.i64(*key)?;
+            e.writer_mut().extend_from_slice(value);
+        }
+        Ok(())
+    })
+}
+
+/// Encode an array from encoded items
+pub fn encode_array(items: &[Vec<u8>]) -> Vec<u8> {
+    cbor(|e| {
+        e.array(items.len() as u64)?;
+        for item in items {
+            e.writer_mut().extend_from_slice(item);
+        }
+        Ok(())
+    })
+}
+
+/// Decode a value, maps must have integer keys
+pub fn value(bytes: &[u8]) -> Result<CborValue, String> {
+    read_value(&mut Decoder::new(bytes)).map_err(|e| e.to_string())
+}
+
+/// The entries of a map with integer keys, the values are left encoded
+pub fn entries(bytes: &[u8]) -> Result<Vec<(i64, &[u8])>, String> {
+    let mut d = Decoder::new(bytes);
+    let mut entries = Vec::new();
+    for _ in 0..map_len(&mut d)? {
+        let key = d.i64().map_err(|e| e.to_string())?;
+        entries.push((key, encoded(&mut d).map_err(|e| e.to_string())?));
+    }
+    Ok(entries)
+}
+
+/// The items of an array, left encoded
+pub fn items(bytes: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let mut d = Decoder::new(bytes);
+    let length = d
+        .array()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| String::from("indefinite array"))?;
+    (0..length)
+        .map(|_| encoded(&mut d).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Encode a tag with an encoded value, the head of a tag is the head of an
+/// unsigned integer with major type 6
+pub fn encode_tagged(tag: u64, value: &[u8]) -> Vec<u8> {
+    let mut out = cbor(|e| {
+        e.u64(tag)?;
+        Ok(())
+    });
+    out[0] |= 0xc0;
+    out.extend_from_slice(value);
+    out
+}
+
+/// The tag of a tagged value and the value it tags, left encoded. None if
+/// the value has no tag
+pub fn tagged(bytes: &[u8]) -> Result<Option<(u64, &[u8])>, String> {
+    let mut d = Decoder::new(bytes);
+    if d.datatype().map_err(|e| e.to_string())? != Type::Tag {
+        return Ok(None);
+    }
+    let tag = tag(&mut d).map_err(|e| e.to_string())?;
+    Ok(Some((tag, &bytes[d.position()..])))
+}
+
+/// The entries of a map with text keys, the values are left encoded
+pub fn text_entries(bytes: &[u8]) -> Result<Vec<(&str, &[u8])>, String> {
+    let mut d = Decoder::new(bytes);
+    let mut entries = Vec::new();
+    for _ in 0..map_len(&mut d)? {
+        let key = d.str().map_err(|e| e.to_string())?;
+        entries.push((key, encoded(&mut d).map_err(|e| e.to_string())?));
+    }
+    Ok(entries)
+}
+
+/// CBOR diagnostic notation of an encoded value, RFC 8949 section 8
+pub fn diagnostic(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    match write_diagnostic(&mut Decoder::new(bytes), &mut out) {
+        Ok(()) => out,
+        Err(_) => format!("h'{}'", hex::encode(bytes)),
+    }
+}
+
+fn write_diagnostic(d: &mut Decoder, out: &mut String) -> Result<(), decode::Error> {
+    let length = |length: Option<u64>| length.ok_or_else(|| decode::Error::message("indefinite"));
+    match d.datatype()? {
+        Type::Array => {
+            out.push('[');
+            for i in 0..length(d.array()?)? {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_diagnostic(d, out)?;
+            }
+            out.push(']');
+        }
+        Type::Map => {
+            out.push('{');
+            for i in 0..length(d.map()?)? {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_diagnostic(d, out)?;
+                out.push_str(": ");
+                write_diagnostic(d, out)?;
+            }
+            out.push('}');
+        }
+        Type::String => {
+            let _ = write!(out, "{:?}", d.str()?);
+        }
+        Type::Bytes => {
+            let _ = write!(out, "h'{}'", hex::encode(d.bytes()?));
+        }
+        Type::Bool => {
+            let _ = write!(out, "{}", d.bool()?);
+        }
+        Type::Null => {
+            d.null()?;
+            out.push_str("null");
+        }
+        Type::Tag => {
+            let _ = write!(out, "{}(", tag(d)?);
+            write_diagnostic(d, out)?;
+            out.push(')');
+        }
+        _ => {
+            let _ = write!(out, "{}", d.i64()?);
+        }
+    }
+    Ok(())
+}
+
+fn read_value(d: &mut Decoder) -> Result<CborValue, decode::Error> {
+    Ok(match d.datatype()? {
+        Type::Bytes => CborValue::Bytes(d.bytes()?.to_vec()),
+        Type::String => CborValue::Text(d.str()?.to_string()),
+        Type::Null => {
+            d.null()?;
+            CborValue::Null
+        }
+        Type::Array => {
+            let length = d
+                .array()?
+                .ok_or_else(|| decode::Error::message("indefinite array"))?;
+            let mut items = Vec::new();
+            for _ in 0..length {
+                items.push(read_value(d)?);
+            }
+            CborValue::Array(items)
+        }
+        Type::Map => {
+            let length = d
+                .map()?
+                .ok_or_else(|| decode::Error::message("indefinite map"))?;
+            let mut map = BTreeMap::new();
+            for _ in 0..length {
+                let key = d.i32()?;
+                map.insert(key, read_value(d)?);
+            }
+            CborValue::Map(map)
+        }
+        _ => CborValue::Integer(d.i64()?),
+    })
+}
+
+fn write_value(
+    e: &mut Encoder<Vec<u8>>,
+    value: &CborValue,
+) -> Result<(), minicbor::encode::Error<Infallible>> {
+    match value {
+        CborValue::Integer(i) => {
+            e.i64(*i)?;
+        }
+        CborValue::Bytes(bytes) => {
+            e.bytes(bytes)?;
+        }
+        CborValue::Text(text) => {
+            e.str(text)?;
+        }
+        CborValue::Array(items) => {
+            e.array(items.len() as u64)?;
+            for item in items {
+                write_value(e, item)?;
+            }
+        }
+        CborValue::Map(map) => {
+            e.map(map.len() as u64)?;
+            for (key, value) in map {
+                e.i32(*key)?;
+                write_value(e, value)?;
+            }
+        }
+        CborValue::Null => {
+            e.null()?;
+        }
+    }
+    Ok(())
+}
+
+// The number of a tag, see encode_tagged
+fn tag(d: &mut Decoder) -> Result<u64, decode::Error> {
+    let start = d.position();
+    d.tag()?;
+    let mut head = d.input()[start..d.position()].to_vec();
+    head[0] &= 0x1f;
+    Decoder::new(&head).u64()
+}
+
+fn map_len(d: &mut Decoder) -> Result<u64, String> {
+    d.map()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| String::from("indefinite map"))
+}
+
+// The next item as it is encoded
+fn encoded<'b>(d: &mut Decoder<'b>) -> Result<&'b [u8], decode::Error> {
+    let start = d.position();
+    d.skip()?;
+    Ok(&d.input()[start..d.position()])
+}
+
+// Writing to a Vec does not fail
+fn cbor(
+    write: impl FnOnce(&mut Encoder<Vec<u8>>) -> Result<(), minicbor::encode::Error<Infallible>>,
+) -> Vec<u8> {
+    let mut e = Encoder::new(Vec::new());
+    write(&mut e).expect("cbor is written to a vec");
+    e.into_writer()
+}
+
+fn invalid(structure: &str, e: impl std::fmt::Display) -> CatTesterError {
+    CatTesterError::Token(format!("invalid {}: {}", structure, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common_access_token::{Algorithm, RegisteredClaims, TokenBuilder};
+
+    /// The MACed CWT of RFC 8392 appendix A.4, HMAC 256/64 with the key of
+    /// appendix A.2.2
+    const RFC8392_TOKEN: &str = "d83dd18443a10104a1044c53796d6d65747269633235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7148093101ef6d789200";
+    const RFC8392_KEY: &str = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+    /// The same token sealed with HMAC 256/256, the tag computed with another
+    /// HMAC implementation
+    const SEALED_TOKEN: &str = "d83dd18443a10105a1044c53796d6d65747269633235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7158202d566152a7b829209f86c6a6539ad7a30b449162a2ee9179a17cc48e05f9db13";
+
+    #[test]
+    fn known_answers() {
+        let token = hex::decode(RFC8392_TOKEN).unwrap();
+        let secret = hex::decode(RFC8392_KEY).unwrap();
+        let message = Message::from_bytes(&token).unwrap();
+        assert_eq!(message.alg().unwrap(), Some(4));
+        let mac = hmac(&secret)
+            .unwrap()
+            .chain_update(message.to_be_signed("MAC0"))
+            .finalize()
+            .into_bytes();
+        assert_eq!(&mac[..8], message.tag.as_slice());
+
+        let opened = open(&token).unwrap();
+        let claims = &opened.claims.registered;
+        assert_eq!(claims.iss.as_deref(), Some("coap://as.example.com"));
+        assert_eq!(claims.sub.as_deref(), Some("erikw"));
+        assert_eq!(claims.aud.as_deref(), Some("coap://light.example.com"));
+        assert_eq!(claims.exp, Some(1444064944));
+        assert_eq!(claims.nbf, Some(1443944944));
+        assert_eq!(claims.iat, Some(1443944944));
+        assert_eq!(claims.cti, Some(vec![0x0b, 0x71]));
+
+        let key = Key::Hmac(secret);
+        let sealed = seal(&token, &key, &[]).unwrap();
+        assert_eq!(hex::encode(&sealed), SEALED_TOKEN);
+        assert!(verify(&sealed, &key).is_ok());
+        // the alg of the token must be the alg of the key
+        assert!(verify(&token, &key).is_err());
+    }
+
+    #[test]
+    fn errors_name_the_structure() {
+        let error = |token: &[u8]| match open(token) {
+            Err(CatTesterError::Token(e)) => e,
+            _ => panic!("token is not invalid"),
+        };
+        assert_eq!(
+            error(&[0xd8, 0x3d, 0xd2, 0x80]),
+            "invalid COSE_Sign1: not an array of four"
+        );
+        assert_eq!(
+            error(&[0xd8, 0x3d, 0xd1, 0x80]),
+            "invalid COSE_Mac0: not an array of four"
+        );
+        assert_eq!(error(&[0x80]), "invalid COSE message: not an array of four");
+    }
+
+    #[test]
+    fn raw_claims_are_sealed_into_the_payload() {
+        let key = Key::Hmac(vec![7; 32]);
+        let prefix = CborValue::Array(vec![
+            CborValue::Integer(32),
+            CborValue::Bytes(vec![0x20, 0x01, 0x0d, 0xb8]),
+        ]);
+        let catnip = encode_array(&[encode_tagged(IPV6_TAG, &encode(&prefix))]);
+        let token = TokenBuilder::new()
+            .algorithm(Algorithm::HmacSha256)
+            .registered_claims(RegisteredClaims::new().with_issuer("eyevinn"))
+            .custom_binary(cat_keys::CATNIP, catnip.clone())
+            .sign(&[0; 32])
+            .unwrap();
+        let sealed = seal(&token.to_bytes().unwrap(), &key, &[]).unwrap();
+
+        let payload = Message::from_bytes(&sealed).unwrap().payload;
+        let claims = entries(&payload).unwrap();
+        let claim = claims.iter().find(|(k, _)| *k == cat_keys::CATNIP as i64);
+        assert_eq!(claim.map(|(_, claim)| *claim), Some(catnip.as_slice()));
+        assert_eq!(diagnostic(&catnip), "[54([32, h'20010db8'])]");
+        assert!(verify(&sealed, &key).is_ok());
+        assert!(verify(&sealed, &Key::Hmac(vec![0; 32])).is_err());
+
+        let opened = open(&sealed).unwrap();
+        assert_eq!(
+            opened.claims.custom.get(&cat_keys::CATNIP),
+            Some(&CborValue::Bytes(catnip))
+        );
+        assert_eq!(opened.claims.registered.iss.as_deref(), Some("eyevinn"));
+
+        let at = sealed.windows(7).position(|w| w == b"eyevinn").unwrap();
+        let mut tampered = sealed.clone();
+        tampered[at] = b'E';
+        assert!(verify(&tampered, &key).is_err());
+    }
+}
@@ -0,0 +1,785 @@
+// Parser for DASH manifests (ISO/IEC 23009-1). Every representation is
+// expanded into a media playlist so the worker can handle HLS and DASH the
+// same way.
+use crate::playlist::{ByteRange, Map, MediaPlaylist, Segment};
+use roxmltree::Node;
+use std::fmt;
+
+/// Segments to list for a live stream without timeShiftBufferDepth
+const DEFAULT_LIVE_WINDOW: f64 = 30.0;
+
+#[derive(Debug)]
+pub struct Mpd {
+    pub representations: Vec<Representation>,
+}
+
+#[derive(Debug)]
+pub struct Representation {
+    pub id: String,
+    pub bandwidth: u64,
+    pub mime_type: Option<String>,
+    pub resolution: Option<String>,
+    pub playlist: MediaPlaylist,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DashError {
+    pub message: String,
+}
+
+impl DashError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for DashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mpd: {}", self.message)
+    }
+}
+
+impl std::error::Error for DashError {}
+
+impl Mpd {
+    /// Video representations, or all of them if the manifest does not say what is video
+    pub fn video_representations(&self) -> Vec<&Representation> {
+        let video: Vec<&Representation> = self
+            .representations
+            .iter()
+            .filter(|r| {
+                r.mime_type
+                    .as_deref()
+                    .is_some_and(|m| m.starts_with("video"))
+            })
+            .collect();
+        if video.is_empty() {
+            self.representations.iter().collect()
+        } else {
+            video
+        }
+    }
+}
+
+/// Check the content type or the start of the body to see if it is a DASH manifest
+pub fn is_mpd(content_type: Option<&str>, body: &str) -> bool {
+    if content_type.is_some_and(|c| c.contains("dash+xml")) {
+        return true;
+    }
+    let body = body.trim_start();
+    body.starts_with("<MPD") || (body.starts_with("<?xml") && body.contains("<MPD"))
+}
+
+pub fn parse(text: &str, now: u64) -> Result<Mpd, DashError> {
+    let document = roxmltree::Document::parse(text).map_err(|e| DashError::new(e.to_string()))?;
+    let mpd = document.root_element();
+    if mpd.tag_name().name() != "MPD" {
+        return Err(DashError::new("missing MPD element"));
+    }
+    let dynamic = mpd.attribute("type") == Some("dynamic");
+    let context = Context {
+        dynamic,
+        now: now as f64,
+        availability_start: mpd
+            .attribute("availabilityStartTime")
+            .map(parse_date_time)
+            .transpose()?,
+        live_window: mpd
+            .attribute("timeShiftBufferDepth")
+            .map(parse_duration)
+            .transpose()?
+            .unwrap_or(DEFAULT_LIVE_WINDOW),
+    };
+    let presentation_duration = mpd
+        .attribute("mediaPresentationDuration")
+        .map(parse_duration)
+        .transpose()?;
+    let mpd_base = base_url("", mpd);
+
+    let mut representations: Vec<Representation> = Vec::new();
+    let mut period_start = 0.0;
+    for period in children(mpd, "Period") {
+        period_start = period
+            .attribute("start")
+            .map(parse_duration)
+            .transpose()?
+            .unwrap_or(period_start);
+        let period_duration = match period.attribute("duration") {
+            Some(duration) => Some(parse_duration(duration)?),
+            None => presentation_duration.map(|d| d - period_start),
+        };
+        let period_base = base_url(&mpd_base, period);
+        let period_template = Template::default().inherit(period);
+        for adaptation_set in children(period, "AdaptationSet") {
+            let set_base = base_url(&period_base, adaptation_set);
+            let set_template = period_template.inherit(adaptation_set);
+            let set_mime = mime_type(adaptation_set);
+            for node in children(adaptation_set, "Representation") {
+                let id = node
+                    .attribute("id")
+                    .ok_or_else(|| DashError::new("Representation without id"))?;
+                let bandwidth = node
+                    .attribute("bandwidth")
+                    .map(|b| parse_number(b, "bandwidth"))
+                    .transpose()?
+                    .unwrap_or(0);
+                let base = base_url(&set_base, node);
+                let template = set_template.inherit(node);
+                let period = PeriodInfo {
+                    start: period_start,
+                    duration: period_duration,
+                };
+                let mut segments = template.segments(&context, &period, &base, id, bandwidth)?;
+                match representations.iter_mut().find(|r| r.id == id) {
+                    // same representation in a later period
+                    Some(representation) => {
+                        if let Some(first) = segments.first_mut() {
+                            first.discontinuity = true;
+                        }
+                        representation.playlist.segments.append(&mut segments);
+                    }
+                    None => representations.push(Representation {
+                        id: id.to_string(),
+                        bandwidth,
+                        mime_type: mime_type(node).or_else(|| set_mime.clone()),
+                        resolution: node
+                            .attribute("width")
+                            .zip(node.attribute("height"))
+                            .map(|(w, h)| format!("{}x{}", w, h)),
+                        playlist: MediaPlaylist {
+                            segments,
+                            ..Default::default()
+                        },
+                    }),
+                }
+            }
+        }
+        if let Some(duration) = period_duration {
+            period_start += duration;
+        }
+    }
+    for representation in representations.iter_mut() {
+        let playlist = &mut representation.playlist;
+        playlist.end_list = !dynamic;
+        playlist.media_sequence = playlist.segments.first().map_or(0, |s| s.sequence);
+        playlist.target_duration = playlist
+            .segments
+            .iter()
+            .map(|s| s.duration.ceil() as u64)
+            .max()
+            .unwrap_or(0);
+    }
+    Ok(Mpd { representations })
+}
+
+struct Context {
+    dynamic: bool,
+    now: f64,
+    availability_start: Option<f64>,
+    live_window: f64,
+}
+
+struct PeriodInfo {
+    start: f64,
+    duration: Option<f64>,
+}
+
+// One entry in a SegmentTimeline, t is filled in from the previous entry when missing
+#[derive(Clone, Debug)]
+struct TimelineEntry {
+    t: Option<u64>,
+    d: u64,
+    r: i64,
+}
+
+// SegmentTemplate, SegmentList and SegmentBase values, the values on a
+// Representation override the ones on AdaptationSet and Period.
+#[derive(Clone, Debug, Default)]
+struct Template {
+    media: Option<String>,
+    initialization: Option<String>,
+    start_number: Option<u64>,
+    timescale: Option<u64>,
+    duration: Option<u64>,
+    presentation_time_offset: Option<u64>,
+    timeline: Option<Vec<TimelineEntry>>,
+    /// SegmentURL media and mediaRange from a SegmentList
+    list: Option<Vec<(Option<String>, Option<String>)>>,
+    initialization_range: Option<String>,
+}
+
+impl Template {
+    fn inherit(&self, node: Node) -> Template {
+        let mut template = self.clone();
+        if let Some(segment_template) = child(node, "SegmentTemplate") {
+            template.read_multiple_segment_base(segment_template);
+            set(&mut template.media, segment_template.attribute("media"));
+            set(
+                &mut template.initialization,
+                segment_template.attribute("initialization"),
+            );
+        }
+        if let Some(segment_list) = child(node, "SegmentList") {
+            template.read_multiple_segment_base(segment_list);
+            if let Some(init) = child(segment_list, "Initialization") {
+                set(&mut template.initialization, init.attribute("sourceURL"));
+                set(&mut template.initialization_range, init.attribute("range"));
+            }
+            let list: Vec<_> = children(segment_list, "SegmentURL")
+                .map(|s| {
+                    (
+                        s.attribute("media").map(String::from),
+                        s.attribute("mediaRange").map(String::from),
+                    )
+                })
+                .collect();
+            if !list.is_empty() {
+                template.list = Some(list);
+            }
+        }
+        if let Some(segment_base) = child(node, "SegmentBase") {
+            if let Some(timescale) = segment_base.attribute("timescale") {
+                template.timescale = timescale.parse().ok();
+            }
+            if let Some(init) = child(segment_base, "Initialization") {
+                set(&mut template.initialization, init.attribute("sourceURL"));
+                set(&mut template.initialization_range, init.attribute("range"));
+            }
+        }
+        template
+    }
+
+    fn read_multiple_segment_base(&mut self, node: Node) {
+        let number = |name: &str| node.attribute(name).and_then(|v| v.parse().ok());
+        if let Some(start_number) = number("startNumber") {
+            self.start_number = Some(start_number);
+        }
+        if let Some(timescale) = number("timescale") {
+            self.timescale = Some(timescale);
+        }
+        if let Some(duration) = number("duration") {
+            self.duration = Some(duration);
+        }
+        if let Some(offset) = number("presentationTimeOffset") {
+            self.presentation_time_offset = Some(offset);
+        }
+        if let Some(timeline) = child(node, "SegmentTimeline") {
+            self.timeline = Some(
+                children(timeline, "S")
+                    .map(|s| TimelineEntry {
+                        t: s.attribute("t").and_then(|v| v.parse().ok()),
+                        d: s.attribute("d").and_then(|v| v.parse().ok()).unwrap_or(0),
+                        r: s.attribute("r").and_then(|v| v.parse().ok()).unwrap_or(0),
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    fn segments(
+        &self,
+        context: &Context,
+        period: &PeriodInfo,
+        base: &str,
+        id: &str,
+        bandwidth: u64,
+    ) -> Result<Vec<Segment>, DashError> {
+        let timescale = self.timescale.unwrap_or(1).max(1);
+        let start_number = self.start_number.unwrap_or(1);
+        let media_uri = |template: &str, number: u64, time: u64| {
+            join_base(
+                base,
+                &substitute(template, id, bandwidth, Some(number), Some(time)),
+            )
+        };
+        let map = self.initialization.as_ref().map(|init| Map {
+            uri: join_base(base, &substitute_init(init, id, bandwidth)),
+            byte_range: self.initialization_range.as_deref().and_then(byte_range),
+        });
+        // (number, time, duration) for every segment
+        let mut entries: Vec<(u64, u64, u64)> = Vec::new();
+
+        let media = if let Some(list) = &self.list {
+            let duration = self.duration.unwrap_or(0);
+            let mut segments = Vec::new();
+            for (index, (media, range)) in list.iter().enumerate() {
+                let number = start_number + index as u64;
+                segments.push(Segment {
+                    uri: media
+                        .as_deref()
+                        .map_or(base.to_string(), |m| join_base(base, m)),
+                    duration: duration as f64 / timescale as f64,
+                    sequence: number,
+                    byte_range: range.as_deref().and_then(byte_range),
+                    map: map.clone(),
+                    ..Default::default()
+                });
+            }
+            return Ok(segments);
+        } else if let Some(media) = &self.media {
+            media
+        } else {
+            // SegmentBase or only a BaseURL, the whole representation is one segment
+            return Ok(vec![Segment {
+                uri: base.to_string(),
+                duration: period.duration.unwrap_or(0.0),
+                sequence: start_number,
+                map,
+                ..Default::default()
+            }]);
+        };
+
+        if let Some(timeline) = &self.timeline {
+            let period_end = period.duration.map(|d| {
+                (d * timescale as f64) as u64 + self.presentation_time_offset.unwrap_or(0)
+            });
+            let live_end = self.live_edge(context, period).map(|edge| {
+                (edge * timescale as f64) as u64 + self.presentation_time_offset.unwrap_or(0)
+            });
+            let mut time = 0;
+            let mut number = start_number;
+            for (index, entry) in timeline.iter().enumerate() {
+                if let Some(t) = entry.t {
+                    time = t;
+                }
+                if entry.d == 0 {
+                    return Err(DashError::new("SegmentTimeline S without d"));
+                }
+                let repeat = if entry.r >= 0 {
+                    entry.r as u64
+                } else {
+                    // repeat until the next S, the end of the period or the live edge
+                    let end = timeline
+                        .get(index + 1)
+                        .and_then(|next| next.t)
+                        .or(period_end)
+                        .or(live_end)
+                        .ok_or_else(|| DashError::new("negative repeat without an end"))?;
+                    (end.saturating_sub(time) / entry.d).saturating_sub(1)
+                };
+                for _ in 0..=repeat {
+                    entries.push((number, time, entry.d));
+                    time += entry.d;
+                    number += 1;
+                }
+            }
+            if context.dynamic
+                && let Some(end) = live_end
+            {
+                let window_start =
+                    end.saturating_sub((context.live_window * timescale as f64) as u64);
+                entries.retain(|&(_, t, d)| t + d <= end && t + d > window_start);
+            }
+        } else {
+            let duration = self
+                .duration
+                .ok_or_else(|| DashError::new("SegmentTemplate without duration or timeline"))?;
+            let seconds = duration as f64 / timescale as f64;
+            let (first, count) = if context.dynamic {
+                let edge = self
+                    .live_edge(context, period)
+                    .ok_or_else(|| DashError::new("dynamic mpd without availabilityStartTime"))?;
+                let available = (edge / seconds).floor() as u64;
+                let window = (context.live_window / seconds).ceil() as u64;
+                let first = available.saturating_sub(window);
+                (first, available - first)
+            } else {
+                let total = period
+                    .duration
+                    .ok_or_else(|| DashError::new("unknown period duration"))?;
+                (0, (total / seconds).ceil() as u64)
+            };
+            for index in first..first + count {
+                entries.push((start_number + index, index * duration, duration));
+            }
+        }
+
+        Ok(entries
+            .into_iter()
+            .map(|(number, time, duration)| Segment {
+                uri: media_uri(media, number, time),
+                duration: duration as f64 / timescale as f64,
+                sequence: number,
+                map: map.clone(),
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    // Seconds since the start of the period that are available for a live stream
+    fn live_edge(&self, context: &Context, period: &PeriodInfo) -> Option<f64> {
+        if !context.dynamic {
+            return None;
+        }
+        let start = context.availability_start? + period.start;
+        Some((context.now - start).max(0.0))
+    }
+}
+
+fn set(target: &mut Option<String>, value: Option<&str>) {
+    if let Some(value) = value {
+        *target = Some(value.to_string());
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn mime_type(node: Node) -> Option<String> {
+    node.attribute("mimeType")
+        .or(node.attribute("contentType"))
+        .map(String::from)
+}
+
+fn base_url(parent: &str, node: Node) -> String {
+    match child(node, "BaseURL").and_then(|n| n.text()) {
+        Some(url) => join_base(parent, url.trim()),
+        None => parent.to_string(),
+    }
+}
+
+// Combine a BaseURL with the one from the parent element, the result is
+// resolved against the manifest url by the worker
+fn join_base(parent: &str, url: &str) -> String {
+    if url.contains("://") || url.starts_with('/') {
+        return url.to_string();
+    }
+    match parent.rfind('/') {
+        Some(pos) => format!("{}{}", &parent[..=pos], url),
+        None => url.to_string(),
+    }
+}
+
+fn substitute_init(template: &str, id: &str, bandwidth: u64) -> String {
+    substitute(template, id, bandwidth, None, None)
+}
+
+// Replace $RepresentationID$, $Bandwidth$, $Number$ and $Time$ with an
+// optional printf width like $Number%05d$, $$ is a literal $
+fn substitute(
+    template: &str,
+    id: &str,
+    bandwidth: u64,
+    number: Option<u64>,
+    time: Option<u64>,
+) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            result.push_str(&rest[start..]);
+            return result;
+        };
+        let identifier = &after[..end];
+        let (name, format) = match identifier.split_once('%') {
+            Some((name, format)) => (name, Some(format)),
+            None => (identifier, None),
+        };
+        let value = match name {
+            "" => Some(String::from("$")),
+            "RepresentationID" => Some(id.to_string()),
+            "Bandwidth" => Some(pad(bandwidth, format)),
+            "Number" => number.map(|n| pad(n, format)),
+            "Time" => time.map(|t| pad(t, format)),
+            _ => None,
+        };
+        match value {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn pad(value: u64, format: Option<&str>) -> String {
+    let width = format
+        .and_then(|f| f.strip_suffix('d'))
+        .and_then(|w| w.trim_start_matches('0').parse::<usize>().ok())
+        .unwrap_or(0);
+    format!("{:0width$}", value, width = width)
+}
+
+// mediaRange="100-199"
+fn byte_range(range: &str) -> Option<ByteRange> {
+    let (first, last) = range.split_once('-')?;
+    let first: u64 = first.trim().parse().ok()?;
+    let last: u64 = last.trim().parse().ok()?;
+    Some(ByteRange {
+        length: last.checked_sub(first)? + 1,
+        offset: Some(first),
+    })
+}
+
+fn parse_number(value: &str, name: &str) -> Result<u64, DashError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| DashError::new(format!("invalid {} {}", name, value)))
+}
+
+/// ISO 8601 duration as used in the MPD, PT1H2M3.5S, to seconds
+pub fn parse_duration(value: &str) -> Result<f64, DashError> {
+    let invalid = || DashError::new(format!("invalid duration {}", value));
+    let rest = value.trim().strip_prefix('P').ok_or_else(invalid)?;
+    let mut seconds = 0.0;
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            _ => {
+                let n: f64 = number.parse().map_err(|_| invalid())?;
+                number.clear();
+                seconds += n * match (c, in_time) {
+                    ('Y', false) => 365.0 * 86400.0,
+                    ('M', false) => 30.0 * 86400.0,
+                    ('W', false) => 7.0 * 86400.0,
+                    ('D', false) => 86400.0,
+                    ('H', true) => 3600.0,
+                    ('M', true) => 60.0,
+                    ('S', true) => 1.0,
+                    _ => return Err(invalid()),
+                };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(seconds)
+}
+
+// xs:dateTime in UTC, 2025-06-17T08:21:08.040000Z, to unix time in seconds
+fn parse_date_time(value: &str) -> Result<f64, DashError> {
+    let invalid = || DashError::new(format!("invalid date time {}", value));
+    let value = value.trim();
+    let value = value
+        .strip_suffix('Z')
+        .or_else(|| value.strip_suffix("+00:00"))
+        .unwrap_or(value);
+    let (date, time) = value.split_once('T').ok_or_else(invalid)?;
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|p| p.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<f64> = time
+        .split(':')
+        .map(|p| p.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    // days from civil, http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Ok(days as f64 * 86400.0 + hour * 3600.0 + minute * 60.0 + second)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // DASH version of the asset in the README, as returned by Unified Streaming
+    const USP_MPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<!-- Created with Unified Streaming Platform  (version=1.13.0-29687) -->
+<MPD
+  xmlns="urn:mpeg:dash:schema:mpd:2011"
+  type="static"
+  mediaPresentationDuration="PT12S"
+  minBufferTime="PT10S"
+  profiles="urn:mpeg:dash:profile:isoff-live:2011">
+  <Period id="1" duration="PT12S">
+    <BaseURL>dash/</BaseURL>
+    <AdaptationSet id="1" group="1" contentType="audio" lang="en" segmentAlignment="true" mimeType="audio/mp4" codecs="mp4a.40.2">
+      <SegmentTemplate timescale="48000" initialization="5245-$RepresentationID$.dash" media="5245-$RepresentationID$-$Time$.dash">
+        <SegmentTimeline>
+          <S t="0" d="184320" r="2" />
+        </SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="audio_eng=128000" bandwidth="128000" audioSamplingRate="48000" />
+    </AdaptationSet>
+    <AdaptationSet id="2" group="2" contentType="video" segmentAlignment="true" mimeType="video/mp4" startWithSAP="1">
+      <SegmentTemplate timescale="25" initialization="5245-$RepresentationID$.dash" media="5245-$RepresentationID$-$Time$.dash">
+        <SegmentTimeline>
+          <S t="0" d="96" />
+          <S d="96" r="1" />
+        </SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="video=799968" bandwidth="799968" width="640" height="360" codecs="avc1.4D401E" />
+      <Representation id="video=5000000" bandwidth="5000000" width="1920" height="1080" codecs="avc1.640028" />
+    </AdaptationSet>
+  </Period>
+</MPD>
+"#;
+
+    #[test]
+    fn parse_usp_mpd_with_segment_timeline() {
+        let mpd = parse(USP_MPD, 0).unwrap();
+        assert_eq!(mpd.representations.len(), 3);
+        let video = mpd.video_representations();
+        assert_eq!(video.len(), 2);
+        let high = video[1];
+        assert_eq!(high.id, "video=5000000");
+        assert_eq!(high.bandwidth, 5000000);
+        assert_eq!(high.resolution.as_deref(), Some("1920x1080"));
+
+        let playlist = &high.playlist;
+        assert!(playlist.end_list);
+        assert_eq!(playlist.target_duration, 4);
+        let uris: Vec<&str> = playlist.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec![
+                "dash/5245-video=5000000-0.dash",
+                "dash/5245-video=5000000-96.dash",
+                "dash/5245-video=5000000-192.dash",
+            ]
+        );
+        assert_eq!(playlist.segments[0].duration, 3.84);
+        assert_eq!(
+            playlist.segments[0].map.as_ref().unwrap().uri,
+            "dash/5245-video=5000000.dash"
+        );
+    }
+
+    #[test]
+    fn parse_number_template_with_base_urls() {
+        let body = r#"<MPD type="static" mediaPresentationDuration="PT10S">
+  <BaseURL>https://cdn.example.com/asset/</BaseURL>
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <BaseURL>video/</BaseURL>
+      <SegmentTemplate timescale="1000" duration="4000" startNumber="5" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%05d$-$Bandwidth$.m4s" />
+      <Representation id="v1" bandwidth="1000">
+        <BaseURL>v1/../</BaseURL>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd = parse(body, 0).unwrap();
+        let playlist = &mpd.representations[0].playlist;
+        assert_eq!(playlist.media_sequence, 5);
+        let uris: Vec<&str> = playlist.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            vec![
+                "https://cdn.example.com/asset/video/v1/../v1/seg-00005-1000.m4s",
+                "https://cdn.example.com/asset/video/v1/../v1/seg-00006-1000.m4s",
+                "https://cdn.example.com/asset/video/v1/../v1/seg-00007-1000.m4s",
+            ]
+        );
+        assert_eq!(
+            playlist.segments[0].map.as_ref().unwrap().uri,
+            "https://cdn.example.com/asset/video/v1/../v1/init.mp4"
+        );
+    }
+
+    #[test]
+    fn parse_segment_list() {
+        let body = r#"<MPD type="static" mediaPresentationDuration="PT20S">
+  <Period>
+    <AdaptationSet>
+      <Representation id="1" bandwidth="500000">
+        <BaseURL>/media/video.mp4</BaseURL>
+        <SegmentList timescale="1" duration="10">
+          <Initialization sourceURL="init.mp4" />
+          <SegmentURL media="seg1.mp4" />
+          <SegmentURL mediaRange="100-199" />
+        </SegmentList>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd = parse(body, 0).unwrap();
+        let segments = &mpd.representations[0].playlist.segments;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].uri, "/media/seg1.mp4");
+        assert_eq!(segments[0].duration, 10.0);
+        assert_eq!(segments[1].uri, "/media/video.mp4");
+        assert_eq!(
+            segments[1].byte_range,
+            Some(ByteRange {
+                length: 100,
+                offset: Some(100)
+            })
+        );
+        assert_eq!(segments[0].map.as_ref().unwrap().uri, "/media/init.mp4");
+    }
+
+    #[test]
+    fn parse_live_number_template() {
+        let body = r#"<MPD type="dynamic" availabilityStartTime="2025-06-17T08:00:00Z" timeShiftBufferDepth="PT12S" minimumUpdatePeriod="PT2S">
+  <Period start="PT0S">
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate duration="4" media="live-$Number$.m4s" />
+      <Representation id="v" bandwidth="1" />
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        // 100 seconds after availabilityStartTime
+        let now = 1750147200 + 100;
+        let mpd = parse(body, now).unwrap();
+        let playlist = &mpd.representations[0].playlist;
+        assert!(!playlist.end_list);
+        let uris: Vec<&str> = playlist.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, vec!["live-23.m4s", "live-24.m4s", "live-25.m4s"]);
+    }
+
+    #[test]
+    fn substitute_identifiers() {
+        assert_eq!(
+            substitute(
+                "$RepresentationID$-$Number%03d$-$Time$$$.m4s",
+                "v1",
+                1,
+                Some(7),
+                Some(99)
+            ),
+            "v1-007-99$.m4s"
+        );
+        assert_eq!(
+            substitute_init("$Unknown$-$Number$", "v1", 1),
+            "$Unknown$-$Number$"
+        );
+    }
+
+    #[test]
+    fn iso_durations() {
+        assert_eq!(parse_duration("PT12S").unwrap(), 12.0);
+        assert_eq!(parse_duration("PT1H2M3.5S").unwrap(), 3723.5);
+        assert_eq!(parse_duration("P1DT1S").unwrap(), 86401.0);
+        assert!(parse_duration("12S").is_err());
+        assert_eq!(
+            parse_date_time("2025-06-17T08:21:08.5Z").unwrap(),
+            1750148468.5
+        );
+    }
+
+    #[test]
+    fn detect_mpd() {
+        assert!(is_mpd(Some("application/dash+xml"), ""));
+        assert!(is_mpd(None, USP_MPD));
+        assert!(!is_mpd(Some("application/vnd.apple.mpegurl"), "#EXTM3U"));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::dash::DashError;
+use crate::playlist::PlaylistError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CatTesterError {
+    /// The key could not be turned into bytes
+    Key(String),
+    /// A url that could not be parsed or resolved
+    Url(String),
+    /// Creating, signing or encoding a token failed
+    Token(String),
+    /// Sending a request or reading the response failed
+    Http(reqwest::Error),
+    /// The manifest could not be parsed or has nothing to fetch
+    Playlist(String),
+    /// A response is missing a header that is needed
+    MissingHeader(String),
+    /// A scenario or other input file could not be read or parsed
+    Config(String),
+    /// Writing the output or serving the mock server failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for CatTesterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatTesterError::Key(message) => write!(f, "invalid key: {}", message),
+            CatTesterError::Url(message) => write!(f, "invalid url: {}", message),
+            CatTesterError::Token(message) => write!(f, "token error: {}", message),
+            CatTesterError::Http(e) => write!(f, "http error: {}", e),
+            CatTesterError::Playlist(message) => write!(f, "playlist error: {}", message),
+            CatTesterError::MissingHeader(message) => write!(f, "missing header: {}", message),
+            CatTesterError::Config(message) => write!(f, "invalid config: {}", message),
+            CatTesterError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CatTesterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CatTesterError::Http(e) => Some(e),
+            CatTesterError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for CatTesterError {
+    fn from(e: reqwest::Error) -> Self {
+        CatTesterError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for CatTesterError {
+    fn from(e: std::io::Error) -> Self {
+        CatTesterError::Io(e)
+    }
+}
+
+impl From<common_access_token::Error> for CatTesterError {
+    fn from(e: common_access_token::Error) -> Self {
+        CatTesterError::Token(e.to_string())
+    }
+}
+
+impl From<PlaylistError> for CatTesterError {
+    fn from(e: PlaylistError) -> Self {
+        CatTesterError::Playlist(e.to_string())
+    }
+}
+
+impl From<DashError> for CatTesterError {
+    fn from(e: DashError) -> Self {
+        CatTesterError::Playlist(e.to_string())
+    }
+}
@@ -0,0 +1,209 @@
+use crate::error::CatTesterError;
+use crate::outcome::Failure;
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Status a request is expected to get, given as `request=status` on the command line
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StatusExpectation {
+    pub request: u32,
+    pub status: u16,
+}
+
+impl FromStr for StatusExpectation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (request, status) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected request=status, got {}", s))?;
+        let request = request
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid request number {}", request))?;
+        let status = status
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid status {}", status))?;
+        Ok(Self { request, status })
+    }
+}
+
+/// What should happen to the requests in a run. Requests without an
+/// expected status must get a 2xx response
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Expectations {
+    #[serde(default)]
+    pub requests: Vec<StatusExpectation>,
+    /// A renewed token must have been received by this request
+    pub renewal_by: Option<u32>,
+}
+
+impl Expectations {
+    /// Read a scenario file, json if the file name ends with .json otherwise toml
+    pub fn from_file(path: &Path) -> Result<Self, CatTesterError> {
+        let invalid = |e: String| CatTesterError::Config(format!("{}: {}", path.display(), e));
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+        {
+            serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))
+        } else {
+            toml::from_str(&text).map_err(|e| invalid(e.to_string()))
+        }
+    }
+
+    /// Add expectations given on the command line, they replace the
+    /// scenario file for the same request
+    pub fn merge(mut self, requests: Vec<StatusExpectation>, renewal_by: Option<u32>) -> Self {
+        for expectation in requests {
+            self.requests.retain(|e| e.request != expectation.request);
+            self.requests.push(expectation);
+        }
+        Self {
+            renewal_by: renewal_by.or(self.renewal_by),
+            ..self
+        }
+    }
+
+    /// Check the response to a request. `timing` has the renewal failures
+    /// from the token clock and `renewed` tells if any response up to and
+    /// including this one had a renewed token
+    pub fn check(
+        &self,
+        request: u32,
+        status: u16,
+        renewed: bool,
+        timing: Vec<Failure>,
+    ) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        match self.requests.iter().find(|e| e.request == request) {
+            Some(expected) => {
+                if expected.status != status {
+                    failures.push(Failure::UnexpectedStatus {
+                        expected: expected.status,
+                        actual: status,
+                    });
+                }
+                // a request expected to be rejected, like one after the token
+                // expired, is not held to the renewal rules
+                if is_success(expected.status) {
+                    failures.extend(timing);
+                }
+            }
+            None => {
+                if !is_success(status) {
+                    failures.push(Failure::Status(status));
+                }
+                failures.extend(timing);
+            }
+        }
+        if self.renewal_by == Some(request) && !renewed {
+            failures.push(Failure::NoRenewalBy(request));
+        }
+        failures
+    }
+
+    /// Requests with an expectation that were never made in a run of `requests` requests
+    pub fn unreached(&self, requests: u32) -> Vec<u32> {
+        let mut unreached: Vec<u32> = self
+            .requests
+            .iter()
+            .map(|e| e.request)
+            .chain(self.renewal_by)
+            .filter(|&r| r > requests)
+            .collect();
+        unreached.sort_unstable();
+        unreached.dedup();
+        unreached
+    }
+}
+
+fn is_success(status: u16) -> bool {
+    (200..300).contains(&status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_status_expectation() {
+        assert_eq!(
+            "6=401".parse::<StatusExpectation>(),
+            Ok(StatusExpectation {
+                request: 6,
+                status: 401
+            })
+        );
+        assert!("6".parse::<StatusExpectation>().is_err());
+        assert!("a=200".parse::<StatusExpectation>().is_err());
+    }
+
+    #[test]
+    fn parse_scenario() {
+        let scenario: Expectations = toml::from_str(
+            "renewal_by = 3\n\
+             [[requests]]\n\
+             request = 1\n\
+             status = 200\n\
+             [[requests]]\n\
+             request = 6\n\
+             status = 401\n",
+        )
+        .unwrap();
+        let json: Expectations = serde_json::from_str(
+            r#"{"renewal_by": 3, "requests": [{"request": 1, "status": 200}, {"request": 6, "status": 401}]}"#,
+        )
+        .unwrap();
+        assert_eq!(scenario, json);
+        assert_eq!(scenario.renewal_by, Some(3));
+        assert_eq!(scenario.requests.len(), 2);
+
+        let merged = scenario.merge(vec!["6=403".parse().unwrap()], None);
+        assert_eq!(merged.renewal_by, Some(3));
+        assert_eq!(
+            merged
+                .requests
+                .iter()
+                .find(|e| e.request == 6)
+                .unwrap()
+                .status,
+            403
+        );
+    }
+
+    #[test]
+    fn check_requests() {
+        let expectations = Expectations::default().merge(vec!["6=401".parse().unwrap()], Some(3));
+        assert!(expectations.check(1, 200, false, vec![]).is_empty());
+        assert_eq!(
+            expectations.check(2, 403, false, vec![]),
+            vec![Failure::Status(403)]
+        );
+        assert_eq!(
+            expectations.check(3, 200, false, vec![]),
+            vec![Failure::NoRenewalBy(3)]
+        );
+        assert!(expectations.check(3, 200, true, vec![]).is_empty());
+        // the expired token is expected to be rejected
+        assert!(
+            expectations
+                .check(6, 401, true, vec![Failure::NotRenewedInTime])
+                .is_empty()
+        );
+        assert_eq!(
+            expectations.check(6, 200, true, vec![]),
+            vec![Failure::UnexpectedStatus {
+                expected: 401,
+                actual: 200
+            }]
+        );
+        assert_eq!(expectations.unreached(4), vec![6]);
+        assert!(expectations.unreached(6).is_empty());
+    }
+}
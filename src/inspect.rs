@@ -0,0 +1,375 @@
+use crate::cose;
+use crate::error::CatTesterError;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use common_access_token::{
+    CborValue, Token, cat_keys, match_types, renewal_params, renewal_types, uri_components,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// COSE header parameters, RFC 9052 section 3.1
+const COSE_HEADERS: [(i32, &str); 6] = [
+    (cose::ALG, "alg"),
+    (cose::CRIT, "crit"),
+    (3, "content type"),
+    (cose::KID, "kid"),
+    (5, "IV"),
+    (6, "Partial IV"),
+];
+
+pub(crate) const CAT_CLAIMS: [(i32, &str); 17] = [
+    (cat_keys::GEOHASH, "geohash"),
+    (cat_keys::CATREPLAY, "catreplay"),
+    (cat_keys::CATPOR, "catpor"),
+    (cat_keys::CATV, "catv"),
+    (cat_keys::CATNIP, "catnip"),
+    (cat_keys::CATU, "catu"),
+    (cat_keys::CATM, "catm"),
+    (cat_keys::CATALPN, "catalpn"),
+    (cat_keys::CATH, "cath"),
+    (cat_keys::CATGEOISO3166, "catgeoiso3166"),
+    (cat_keys::CATGEOCOORD, "catgeocoord"),
+    (cat_keys::CATGEOALT, "catgeoalt"),
+    (cat_keys::CATTPK, "cattpk"),
+    (cat_keys::CATIFDATA, "catifdata"),
+    (cat_keys::CATDPOP, "catdpop"),
+    (cat_keys::CATIF, "catif"),
+    (cat_keys::CATR, "catr"),
+];
+
+pub(crate) const RENEWAL_PARAMS: [(i32, &str); 8] = [
+    (renewal_params::TYPE, "type"),
+    (renewal_params::EXPADD, "exp-add"),
+    (renewal_params::DEADLINE, "deadline"),
+    (renewal_params::COOKIE_NAME, "cookie-name"),
+    (renewal_params::HEADER_NAME, "header-name"),
+    (renewal_params::COOKIE_PARAMS, "cookie-params"),
+    (renewal_params::HEADER_PARAMS, "header-params"),
+    (renewal_params::CODE, "code"),
+];
+
+pub(crate) const URI_COMPONENTS: [(i32, &str); 9] = [
+    (uri_components::SCHEME, "scheme"),
+    (uri_components::HOST, "host"),
+    (uri_components::PORT, "port"),
+    (uri_components::PATH, "path"),
+    (uri_components::QUERY, "query"),
+    (uri_components::PARENT_PATH, "parent-path"),
+    (uri_components::FILENAME, "filename"),
+    (uri_components::STEM, "stem"),
+    (uri_components::EXTENSION, "extension"),
+];
+
+pub(crate) const MATCH_TYPES: [(i32, &str); 7] = [
+    (match_types::EXACT, "exact"),
+    (match_types::PREFIX, "prefix"),
+    (match_types::SUFFIX, "suffix"),
+    (match_types::CONTAINS, "contains"),
+    (match_types::REGEX, "regex"),
+    (match_types::SHA256, "sha-256"),
+    (match_types::SHA512_256, "sha-512/256"),
+];
+
+/// Decode a base64url token as it is sent in a header, cookie or query
+pub fn decode(token: &str) -> Result<Token, CatTesterError> {
+    cose::open(&token_bytes(token)?)
+}
+
+/// The COSE bytes of a base64url token, the tag is checked on these
+pub fn token_bytes(token: &str) -> Result<Vec<u8>, CatTesterError> {
+    let token = token.trim().trim_end_matches('=');
+    URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|e| CatTesterError::Token(format!("token is not base64url: {}", e)))
+}
+
+/// Human readable description of a token, times are shown relative to `now`
+pub fn describe(token: &Token, now: u64) -> String {
+    let mut out = String::new();
+    out.push_str("Protected header\n");
+    for (key, value) in &token.header.protected {
+        write_header(&mut out, *key, value);
+    }
+    out.push_str("Unprotected header\n");
+    for (key, value) in &token.header.unprotected {
+        write_header(&mut out, *key, value);
+    }
+
+    let registered = &token.claims.registered;
+    out.push_str("Registered claims\n");
+    if let Some(iss) = &registered.iss {
+        let _ = writeln!(out, "  iss (1): {}", iss);
+    }
+    if let Some(sub) = &registered.sub {
+        let _ = writeln!(out, "  sub (2): {}", sub);
+    }
+    if let Some(aud) = &registered.aud {
+        let _ = writeln!(out, "  aud (3): {}", aud);
+    }
+    if let Some(exp) = registered.exp {
+        let _ = writeln!(out, "  exp (4): {}", describe_time(exp, now));
+    }
+    if let Some(nbf) = registered.nbf {
+        let _ = writeln!(out, "  nbf (5): {}", describe_time(nbf, now));
+    }
+    if let Some(iat) = registered.iat {
+        let _ = writeln!(out, "  iat (6): {}", describe_time(iat, now));
+    }
+    if let Some(cti) = &registered.cti {
+        let _ = writeln!(out, "  cti (7): {}", hex::encode(cti));
+    }
+
+    out.push_str("CAT claims\n");
+    for (key, value) in &token.claims.custom {
+        let name = name_of(&CAT_CLAIMS, *key).unwrap_or("unknown");
+        let _ = write!(out, "  {} ({}):", name, key);
+        match *key {
+            cat_keys::CATR => write_catr(&mut out, value, now),
+            cat_keys::CATU => write_catu(&mut out, value),
+            cat_keys::CATNIP | cat_keys::CATH | cat_keys::CATIF => write_raw(&mut out, value),
+            _ => write_value(&mut out, value, 2),
+        }
+    }
+    let _ = writeln!(out, "Tag: {}", hex::encode(&token.signature));
+    out
+}
+
+fn write_header(out: &mut String, key: i32, value: &CborValue) {
+    let name = name_of(&COSE_HEADERS, key).unwrap_or("unknown");
+    let _ = write!(out, "  {} ({}):", name, key);
+    match (key, value) {
+        (cose::ALG, CborValue::Integer(alg)) => {
+            let _ = writeln!(out, " {} ({})", algorithm_name(*alg), alg);
+        }
+        _ => write_value(out, value, 2),
+    }
+}
+
+// COSE algorithms, RFC 9053
+fn algorithm_name(alg: i64) -> &'static str {
+    match alg {
+        4 => "HMAC 256/64",
+        5 => "HMAC 256/256",
+        6 => "HMAC 384/384",
+        7 => "HMAC 512/512",
+        -7 => "ES256",
+        -8 => "EdDSA",
+        _ => "unknown",
+    }
+}
+
+fn write_catr(out: &mut String, value: &CborValue, now: u64) {
+    let CborValue::Map(params) = value else {
+        write_value(out, value, 2);
+        return;
+    };
+    out.push('\n');
+    for (key, value) in params {
+        let name = name_of(&RENEWAL_PARAMS, *key).unwrap_or("unknown");
+        let _ = write!(out, "    {} ({}):", name, key);
+        match (*key, value) {
+            (renewal_params::TYPE, CborValue::Integer(kind)) => {
+                let _ = writeln!(out, " {} ({})", renewal_type_name(*kind), kind);
+            }
+            (renewal_params::DEADLINE, CborValue::Integer(deadline)) if *deadline >= 0 => {
+                let _ = writeln!(out, " {}", describe_time(*deadline as u64, now));
+            }
+            _ => write_value(out, value, 4),
+        }
+    }
+}
+
+fn renewal_type_name(kind: i64) -> &'static str {
+    match kind {
+        renewal_types::AUTOMATIC => "automatic",
+        renewal_types::COOKIE => "cookie",
+        renewal_types::HEADER => "header",
+        renewal_types::REDIRECT => "redirect",
+        _ => "unknown",
+    }
+}
+
+fn write_catu(out: &mut String, value: &CborValue) {
+    let CborValue::Map(components) = value else {
+        write_value(out, value, 2);
+        return;
+    };
+    out.push('\n');
+    for (component, matches) in components {
+        let name = name_of(&URI_COMPONENTS, *component).unwrap_or("unknown");
+        let _ = writeln!(out, "    {} ({}):", name, component);
+        let CborValue::Map(matches) = matches else {
+            write_value(out, matches, 6);
+            continue;
+        };
+        for (kind, value) in matches {
+            let name = name_of(&MATCH_TYPES, *kind).unwrap_or("unknown");
+            let _ = write!(out, "      {} ({}):", name, kind);
+            write_value(out, value, 6);
+        }
+    }
+}
+
+// A claim the token library can not decode is the encoded claim, see
+// cose::open
+fn write_raw(out: &mut String, value: &CborValue) {
+    match value {
+        CborValue::Bytes(raw) => {
+            let _ = writeln!(out, " {}", cose::diagnostic(raw));
+        }
+        _ => write_value(out, value, 2),
+    }
+}
+
+// Write a value after a label, maps on the lines below and everything
+// else on the same line
+fn write_value(out: &mut String, value: &CborValue, indent: usize) {
+    match value {
+        CborValue::Map(map) => {
+            out.push('\n');
+            write_map(out, map, indent + 2);
+        }
+        _ => {
+            let _ = writeln!(out, " {}", format_value(value));
+        }
+    }
+}
+
+fn write_map(out: &mut String, map: &BTreeMap<i32, CborValue>, indent: usize) {
+    for (key, value) in map {
+        let _ = write!(out, "{:indent$}{}:", "", key, indent = indent);
+        write_value(out, value, indent);
+    }
+}
+
+fn format_value(value: &CborValue) -> String {
+    match value {
+        CborValue::Integer(i) => i.to_string(),
+        CborValue::Bytes(bytes) => format!("h'{}'", hex::encode(bytes)),
+        CborValue::Text(text) => format!("{:?}", text),
+        CborValue::Array(items) => {
+            let items: Vec<String> = items.iter().map(format_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        CborValue::Map(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, format_value(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        CborValue::Null => String::from("null"),
+    }
+}
+
+fn name_of(names: &[(i32, &'static str)], key: i32) -> Option<&'static str> {
+    names.iter().find(|(k, _)| *k == key).map(|(_, name)| *name)
+}
+
+/// Key for a name in one of the name tables, the reverse of name_of
+pub(crate) fn key_of(names: &[(i32, &'static str)], name: &str) -> Option<i32> {
+    names
+        .iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|(key, _)| *key)
+}
+
+/// A unix time as seconds, date and how long ago or ahead of `now` it is
+pub fn describe_time(time: u64, now: u64) -> String {
+    let relative = if time > now {
+        format!("in {}s", time - now)
+    } else {
+        format!("{}s ago", now - time)
+    };
+    format!("{} ({}, {})", time, format_time(time), relative)
+}
+
+// UTC date and time of a unix time, civil from days,
+// http://howardhinnant.github.io/date_algorithms.html
+fn format_time(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let seconds = time % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claims::ClaimSet;
+    use crate::token::{self, TokenType};
+    use common_access_token::current_timestamp;
+
+    #[test]
+    fn format_unix_times() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(1750148468), "2025-06-17 08:21:08 UTC");
+        assert_eq!(format_time(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(
+            describe_time(110, 100),
+            "110 (1970-01-01 00:01:50 UTC, in 10s)"
+        );
+        assert_eq!(
+            describe_time(90, 100),
+            "90 (1970-01-01 00:01:30 UTC, 10s ago)"
+        );
+    }
+
+    #[test]
+    fn describe_created_token() {
+        let key = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+        let bytes = token::create_token(
+            &token::load_key(key).unwrap(),
+            20,
+            &TokenType::Header,
+            ".example.com",
+            "eyevinn",
+            &ClaimSet::default(),
+            current_timestamp(),
+        )
+        .unwrap();
+        let decoded = decode(&URL_SAFE_NO_PAD.encode(&bytes)).unwrap();
+        let iat = decoded.claims.registered.iat.unwrap();
+        let text = describe(&decoded, iat);
+        assert!(text.contains("  alg (1): HMAC 256/256 (5)\n"));
+        assert!(text.contains("  kid (4): \"Symmetric256\"\n"));
+        assert!(text.contains("  iss (1): eyevinn\n"));
+        assert!(text.contains(&format!("  exp (4): {}", describe_time(iat + 40, iat))));
+        assert!(text.contains(&format!(
+            "  cti (7): {}\n",
+            hex::encode(decoded.claims.registered.cti.as_ref().unwrap())
+        )));
+        assert!(text.contains("  catr (323):\n    type (0): header (2)\n    exp-add (1): 20\n"));
+        assert!(text.contains(&format!(
+            "    deadline (2): {}",
+            describe_time(iat + 10, iat)
+        )));
+        assert!(text.contains("    header-name (4): \"CTA-Common-Access-Token\"\n"));
+    }
+
+    #[test]
+    fn invalid_token_is_rejected() {
+        assert!(matches!(
+            decode("not base64!"),
+            Err(CatTesterError::Token(_))
+        ));
+        assert!(matches!(decode("AAAA"), Err(CatTesterError::Token(_))));
+    }
+}
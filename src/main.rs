@@ -1,16 +1,65 @@
-use clap::Parser;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use claims::{
+    ClaimSet, HeaderMatch, Network, Renewal, RenewalType, Replay, ResponseHeader, UriMatch,
+};
+use clap::{Args, Parser, Subcommand};
+use common_access_token::current_timestamp;
+use error::CatTesterError;
+use expect::{Expectations, StatusExpectation};
+use reqwest::header::{HeaderName, HeaderValue};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+mod claims;
 mod client;
+mod clock;
+mod cose;
+mod dash;
+mod error;
+mod expect;
+mod inspect;
+mod outcome;
+mod playlist;
+mod server;
 mod token;
+mod verify;
+
+/// Works with the online checker https://cta-token.net/
+const DEFAULT_KEY: &str = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
-    /// Key to use, default work with the online checker https://cta-token.net/
-    #[arg(
-        short,
-        long,
-        default_value_t = String::from("403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388")
-    )]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a token and print it
+    Generate(GenerateArgs),
+    /// Decode a base64url token and show its headers and claims, no key is needed
+    Inspect {
+        /// Token as sent in the header, cookie or query
+        token: String,
+    },
+    /// Check the MAC of a token and whether its claims accept a request
+    Verify(VerifyArgs),
+    /// Fetch a playlist and its segments with a token like a player does
+    Run(RunArgs),
+    /// Start a mock CDN that serves a playlist to requests with a valid token
+    Serve(ServeArgs),
+}
+
+/// Options for the token, shared by generate and run
+#[derive(Args, Debug)]
+struct TokenArgs {
+    /// Key as hex or a file with the key as hex, an oct JWK or a P-256 or
+    /// Ed25519 private key as PEM or JWK, the default works with the online
+    /// checker https://cta-token.net/
+    #[arg(short, long, default_value_t = String::from(DEFAULT_KEY))]
     key: String,
 
     /// exp to add in renewal, token expier is set to ttl times two
@@ -23,37 +72,570 @@ struct Args {
     #[arg(value_enum,short,long,default_value_t = token::TokenType::Header)]
     token_type: token::TokenType,
 
-    /// m3u8 url that return streaming segments
-    #[arg(short, long)]
-    url: String,
-
     /// token issuer to use
     #[arg(short,long,default_value_t=String::from("eyevinn"))]
     issuer: String,
 
-    /// Number of times to fetch the segment
-    #[arg(short, long, default_value_t = 5)]
-    max_iterations: u32,
+    /// Toml or json file with the registered and CAT claims of the token
+    #[arg(long)]
+    claims: Option<PathBuf>,
+
+    /// Restrict the token to urls with a catu match as component:match:value,
+    /// for example path:prefix:/live/. Components are scheme, host, port, path,
+    /// query, parent-path, filename, stem and extension, matches are exact,
+    /// prefix, suffix, contains, regex and sha256. Can be given more than once
+    #[arg(long)]
+    catu: Vec<UriMatch>,
+
+    /// Methods the token allows as catm, for example GET,HEAD
+    #[arg(long, value_delimiter = ',')]
+    catm: Vec<String>,
+
+    /// Networks the token is valid from as catnip, addresses, prefixes like
+    /// 10.0.0.0/8 or ASNs like AS64500
+    #[arg(long, value_delimiter = ',')]
+    catnip: Vec<Network>,
+
+    /// ISO 3166 country or subdivision codes the token is valid in as
+    /// catgeoiso3166, for example SE,US-CA
+    #[arg(long, value_delimiter = ',')]
+    catgeoiso3166: Vec<String>,
+
+    /// Geohash cells the token is valid in as the geohash claim, for example u6sc
+    #[arg(long, alias = "catgeohash", value_delimiter = ',')]
+    geohash: Vec<String>,
+
+    /// How the CDN handles a token that is used again, as catreplay
+    #[arg(value_enum, long)]
+    catreplay: Option<Replay>,
+
+    /// Request header the token requires as a cath match header:match:value,
+    /// for example User-Agent:prefix:Mozilla. Can be given more than once
+    #[arg(long)]
+    cath: Vec<HeaderMatch>,
+
+    /// CAT version for the catv claim, the tester and mock CDN only know 1
+    #[arg(long)]
+    catv: Option<i64>,
+
+    /// Label of a COSE header parameter the CDN must understand, put in the
+    /// protected crit header. A kid in crit is put in the protected header.
+    /// For example 4 for kid. Can be given more than once
+    #[arg(long, value_delimiter = ',', allow_negative_numbers = true)]
+    crit: Vec<i64>,
+
+    /// Status the CDN answers with instead of rejecting the token, the catif
+    /// claim. For example 302 with a Location in --catif-header
+    #[arg(long)]
+    catif: Option<u16>,
+
+    /// Header in the catif response as name: value. Can be given more than once
+    #[arg(long, requires = "catif")]
+    catif_header: Vec<ResponseHeader>,
+
+    /// Text for the catifdata claim, the mock CDN puts it in place of
+    /// {catifdata} in the catif headers
+    #[arg(long)]
+    catifdata: Option<String>,
+
+    /// How the CDN should renew the token, default header for the header
+    /// token type and cookie otherwise
+    #[arg(value_enum, long)]
+    renewal: Option<RenewalType>,
+
+    /// Seconds a renewed token is valid, exp-add in the catr claim. Default ttl
+    #[arg(long)]
+    renewal_exp_add: Option<u64>,
+
+    /// Seconds after iat the token should be renewed, the catr deadline.
+    /// Default half the ttl
+    #[arg(long)]
+    renewal_deadline: Option<u64>,
+
+    /// Name of the renewal cookie or header, default CTA-Common-Access-Token
+    #[arg(long)]
+    renewal_name: Option<String>,
+
+    /// Cookie or header parameter for the renewal, like HttpOnly. Can be
+    /// given more than once and replaces the default cookie parameters
+    #[arg(long)]
+    renewal_param: Vec<String>,
+
+    /// Status of a redirect renewal, like 302 or 307
+    #[arg(long)]
+    renewal_code: Option<u16>,
+}
+
+impl TokenArgs {
+    fn claim_set(&self) -> Result<ClaimSet, CatTesterError> {
+        let claims = match &self.claims {
+            Some(path) => ClaimSet::from_file(path)?,
+            None => ClaimSet::default(),
+        };
+        Ok(claims
+            .with_catu(self.catu.clone())
+            .with_catm(self.catm.clone())
+            .with_catnip(self.catnip.clone())
+            .with_catgeoiso3166(self.catgeoiso3166.clone())
+            .with_geohash(self.geohash.clone())
+            .with_catreplay(self.catreplay)
+            .with_cath(self.cath.clone())
+            .with_catv(self.catv)
+            .with_crit(self.crit.clone())
+            .with_catif(self.catif, self.catif_header.clone())
+            .with_catifdata(self.catifdata.clone())
+            .with_renewal(Renewal {
+                kind: self.renewal,
+                exp_add: self.renewal_exp_add,
+                deadline: self.renewal_deadline,
+                name: self.renewal_name.clone(),
+                params: (!self.renewal_param.is_empty()).then(|| self.renewal_param.clone()),
+                code: self.renewal_code,
+            }))
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+enum OutputFormat {
+    /// The CBOR bytes as they are
+    Raw,
+    /// As sent in the header, cookie or query
+    Base64url,
+    Hex,
+    /// A curl command line that sends the token to the url
+    Curl,
+}
+
+#[derive(Args, Debug)]
+struct GenerateArgs {
+    #[command(flatten)]
+    token: TokenArgs,
+
+    /// Url the token is for, the cookie domain is taken from it. Needed for curl
+    #[arg(short, long)]
+    url: Option<String>,
+
+    /// How to print the token
+    #[arg(value_enum, short, long, default_value_t = OutputFormat::Base64url)]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Token as sent in the header, cookie or query
+    token: String,
+
+    /// Key as hex or a key file, like for a run
+    #[arg(short, long, default_value_t = String::from(DEFAULT_KEY))]
+    key: String,
+
+    /// Url of the request, checked against catu
+    #[arg(short, long)]
+    url: Option<String>,
+
+    /// Method of the request, checked against catm
+    #[arg(long, default_value = "GET")]
+    method: String,
+
+    /// Ip address of the client, checked against catnip
+    #[arg(long)]
+    client_ip: Option<IpAddr>,
+
+    /// ISO 3166 code of where the client is, checked against catgeoiso3166
+    #[arg(long)]
+    country: Option<String>,
+
+    /// Geohash of where the client is, checked against the geohash claim
+    #[arg(long)]
+    geohash: Option<String>,
+
+    /// Header of the request as "name: value", checked against cath. Can be given more than once
+    #[arg(short = 'H', long = "header", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    token: TokenArgs,
+
+    /// m3u8 or mpd url that return streaming segments
+    #[arg(short, long)]
+    url: String,
+
+    /// Number of times to fetch the segment, or number of segments to fetch in live mode,
+    /// default 5 unless a live run is limited by --duration
+    #[arg(short, long)]
+    max_iterations: Option<u32>,
+
+    /// How to fetch segments from the playlist
+    #[arg(value_enum, long, default_value_t = client::Mode::Repeat)]
+    mode: client::Mode,
+
+    /// Stop a live or vod run after this many seconds
+    #[arg(long)]
+    duration: Option<u64>,
+
+    /// Playback speed in vod mode, 2 plays twice as fast and 0 fetches without waiting
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
 
     /// time in ms to sleep between fething stream segment
     #[arg(long, default_value_t = 4000)]
     sleep: u64,
+
+    /// Method for segment, init and key requests, playlists are fetched with GET.
+    /// Options sends a CORS preflight without the token
+    #[arg(value_enum, long, default_value_t = client::RequestMethod::Get)]
+    method: client::RequestMethod,
+
+    /// Header to send with every request as "name: value", replaces the default
+    /// User-Agent. Can be given more than once
+    #[arg(short = 'H', long = "header", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Local address to make the requests from, on a host with more than one address
+    #[arg(long)]
+    local_address: Option<IpAddr>,
+
+    /// Keep the query parameters of the url on segment, init, key and variant urls
+    #[arg(long)]
+    keep_query: bool,
+
+    /// Variant to use if the url is a master playlist:
+    /// highest, lowest, a bandwidth or text matching the variant uri or resolution
+    #[arg(long, default_value = "highest")]
+    variant: client::VariantSelector,
+
+    /// Expected status for a request as request=status, for example 6=401.
+    /// Can be given more than once, requests without one must get a 2xx status
+    #[arg(long = "expect-status")]
+    expect_status: Vec<StatusExpectation>,
+
+    /// A renewed token must have been received by this request
+    #[arg(long)]
+    expect_renewal_by: Option<u32>,
+
+    /// Toml or json file with expectations, the command line options replace it
+    #[arg(long)]
+    scenario: Option<PathBuf>,
+
+    /// After the run send the first token again from a second worker with its
+    /// own HTTP client and cookie jar. It must be rejected if catreplay is
+    /// prohibited and accepted otherwise
+    #[arg(long)]
+    replay: bool,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Key the tokens are signed with, as hex or a key file like for a run
+    #[arg(short, long, default_value_t = String::from(DEFAULT_KEY))]
+    key: String,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Number of segments in the playlist
+    #[arg(long, default_value_t = 10)]
+    segments: u32,
+
+    /// Duration of each segment in seconds
+    #[arg(long, default_value_t = 4)]
+    segment_duration: u64,
+
+    /// Pretend every client is in this ISO 3166 country or subdivision, for catgeoiso3166
+    #[arg(long)]
+    country: Option<String>,
+
+    /// Pretend every client is at this geohash, for the geohash claim
+    #[arg(long)]
+    geohash: Option<String>,
+
+    /// Log every request with the response status, and tokens with catreplay
+    /// that are used again
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// Exit code when a request in a run failed, see outcome::Failure, or a
+/// verified token is not valid
+const EXIT_REQUEST_FAILED: i32 = 1;
+
+/// Segment fetches in a run when --max-iterations is not given
+const DEFAULT_MAX_ITERATIONS: u32 = 5;
+
+/// Exit code for each kind of failure so scripts can tell them apart
+fn exit_code(error: &CatTesterError) -> i32 {
+    match error {
+        CatTesterError::Key(_) => 3,
+        CatTesterError::Url(_) => 4,
+        CatTesterError::Token(_) => 5,
+        CatTesterError::Http(_) => 6,
+        CatTesterError::Playlist(_) => 7,
+        CatTesterError::MissingHeader(_) => 8,
+        CatTesterError::Config(_) => 9,
+        CatTesterError::Io(_) => 10,
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Generate(args) => generate(args),
+        Command::Inspect { token } => inspect_token(&token),
+        Command::Verify(args) => verify_token(args),
+        Command::Run(args) => run(args).await,
+        Command::Serve(args) => serve(args).await,
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(EXIT_REQUEST_FAILED),
+        Err(e) => {
+            eprintln!("Failed: {}", e);
+            std::process::exit(exit_code(&e));
+        }
+    }
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("expected name: value, got {}", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|e| format!("invalid header name {}: {}", name, e))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|e| format!("invalid header value {}: {}", value, e))?;
+    Ok((name, value))
+}
+
+fn parse_url(url: &str) -> Result<reqwest::Url, CatTesterError> {
+    url.parse()
+        .map_err(|e| CatTesterError::Url(format!("{}: {}", url, e)))
+}
+
+fn generate(args: GenerateArgs) -> Result<bool, CatTesterError> {
+    let url = args.url.as_deref().map(parse_url).transpose()?;
+    let host = url
+        .as_ref()
+        .and_then(|u| u.host_str())
+        .unwrap_or("localhost");
+    let domain = client::Worker::extract_cookie_domain(host).unwrap_or_else(|| host.to_string());
+    let bytes = token::create_token(
+        &token::load_key(&args.token.key)?,
+        args.token.ttl,
+        &args.token.token_type,
+        &domain,
+        &args.token.issuer,
+        &args.token.claim_set()?,
+        current_timestamp(),
+    )?;
+    let encoded = URL_SAFE_NO_PAD.encode(&bytes);
+    let mut stdout = std::io::stdout();
+    match args.format {
+        OutputFormat::Raw => stdout.write_all(&bytes)?,
+        OutputFormat::Base64url => writeln!(stdout, "{}", encoded)?,
+        OutputFormat::Hex => writeln!(stdout, "{}", hex::encode(&bytes))?,
+        OutputFormat::Curl => {
+            let url = url.ok_or_else(|| {
+                CatTesterError::Url(String::from("--url is needed for the curl format"))
+            })?;
+            let command = curl_command(url, &args.token.token_type, &encoded);
+            writeln!(stdout, "{}", command)?
+        }
+    }
+    stdout.flush()?;
+    Ok(true)
+}
+
+// curl command line that sends the token the way the token type does
+fn curl_command(mut url: reqwest::Url, token_type: &token::TokenType, token: &str) -> String {
+    match token_type {
+        token::TokenType::Header => {
+            format!("curl -v -H 'CTA-Common-Access-Token: {}' '{}'", token, url)
+        }
+        token::TokenType::Cookie => {
+            format!(
+                "curl -v --cookie 'CTA-Common-Access-Token={}' '{}'",
+                token, url
+            )
+        }
+        token::TokenType::CookieAsQuery | token::TokenType::Query => {
+            url.query_pairs_mut().append_pair("CAT", token);
+            format!("curl -v '{}'", url)
+        }
+    }
+}
+
+fn inspect_token(token: &str) -> Result<bool, CatTesterError> {
+    let token = inspect::decode(token)?;
+    print!("{}", inspect::describe(&token, current_timestamp()));
+    Ok(true)
+}
+
+// Returns false if the token is not valid for the request
+fn verify_token(args: VerifyArgs) -> Result<bool, CatTesterError> {
+    let key = token::load_key(&args.key)?;
+    let token = inspect::token_bytes(&args.token)?;
+    let request = verify::RequestInfo {
+        url: args.url.as_deref().map(parse_url).transpose()?,
+        method: Some(args.method),
+        client_ip: args.client_ip,
+        country: args.country,
+        geohash: args.geohash,
+        headers: (!args.headers.is_empty()).then(|| {
+            args.headers
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    (name.to_string(), value)
+                })
+                .collect()
+        }),
+    };
+    let verification = verify::verify(&token, &key, &request, current_timestamp())?;
+    print!("{}", verification);
+    if verification.valid() {
+        println!("Token is valid");
+    } else {
+        let mut reasons: Vec<&str> = verification.failed().map(|c| c.claim).collect();
+        if verification.mac.is_err() {
+            reasons.insert(0, "mac");
+        }
+        println!("Token is NOT valid: {}", reasons.join(", "));
+    }
+    Ok(verification.valid())
+}
+
+// Returns false if any request failed
+async fn run(args: RunArgs) -> Result<bool, CatTesterError> {
+    let expectations = match &args.scenario {
+        Some(path) => Expectations::from_file(path)?,
+        None => Expectations::default(),
+    }
+    .merge(args.expect_status, args.expect_renewal_by);
+    let claims = args.token.claim_set()?;
+    // a live run with only a duration plays until the duration is reached
+    let max_iterations = match args.max_iterations {
+        Some(max_iterations) => max_iterations,
+        None if args.mode == client::Mode::Live && args.duration.is_some() => u32::MAX,
+        None => DEFAULT_MAX_ITERATIONS,
+    };
+    let prohibited = claims.catreplay == Some(Replay::Prohibited);
     let worker = client::Worker::new(
-        &args.key,
+        &args.token.key,
         &args.url,
-        args.ttl,
-        args.token_type,
-        &args.issuer,
-        args.max_iterations,
+        args.token.ttl,
+        args.token.token_type,
+        &args.token.issuer,
+        max_iterations,
         args.sleep,
+    )?
+    .with_claims(claims)?
+    .with_local_address(args.local_address)?
+    .with_variant(args.variant)
+    .with_mode(args.mode)
+    .with_duration(args.duration.map(Duration::from_secs))
+    .with_speed(args.speed)
+    .with_keep_query(args.keep_query)
+    .with_method(args.method)
+    .with_headers(args.headers.into_iter().collect())
+    .with_expectations(expectations.clone());
+    let report = worker.run().await?;
+    println!("Worker completed all requests");
+    for outcome in report.failed() {
+        let failures: Vec<String> = outcome.failures.iter().map(|f| f.to_string()).collect();
+        println!(
+            "Request {} failed: {} ({} {})",
+            outcome.request,
+            failures.join(", "),
+            outcome.status,
+            outcome.url
+        );
+    }
+    for outcome in &report.outcomes {
+        for redirect in &outcome.redirects {
+            println!("Request {} redirected: {}", outcome.request, redirect);
+        }
+    }
+    match report.renewed_in_time() {
+        Some(true) => println!("Token renewed before it expired"),
+        Some(false) => println!("Token expired before it was renewed"),
+        None => {}
+    }
+    let unreached = expectations.unreached(report.outcomes.len() as u32);
+    for request in &unreached {
+        println!("Request {} failed: expected but never made", request);
+    }
+    println!(
+        "{} of {} requests passed, {} renewed tokens",
+        report.outcomes.len() - report.failed().count(),
+        report.outcomes.len(),
+        report.outcomes.iter().filter(|o| o.renewed).count()
     );
-    match worker.run().await {
-        Ok(_) => println!("Worker completed all requests"),
-        Err(e) => eprintln!("Worker failed: {}", e),
+    let replayed = !args.replay || replay(&worker, prohibited).await?;
+    Ok(report.passed() && unreached.is_empty() && replayed)
+}
+
+// Run a second worker with the first token, returns false if the CDN did not
+// handle the replay the way catreplay asks for
+async fn replay(worker: &client::Worker, prohibited: bool) -> Result<bool, CatTesterError> {
+    let exp = inspect::decode(worker.token())?.claims.registered.exp;
+    if exp.is_some_and(|exp| exp <= current_timestamp()) {
+        println!(
+            "Replay failed: the first token expired before it was replayed, use a shorter run or a longer ttl"
+        );
+        return Ok(false);
     }
+    println!("Replaying the first token from a second client");
+    // the expectations are for the first client, the second client only
+    // has to be accepted for every request or rejected
+    let report = worker
+        .independent()?
+        .with_expectations(Expectations::default())
+        .run()
+        .await?;
+    let rejected_with = report.failed().next().map(|outcome| outcome.status);
+    let passed = match (rejected_with, prohibited) {
+        (Some(status), true) => {
+            println!(
+                "Replay passed: the replayed token was rejected with {}",
+                status
+            );
+            true
+        }
+        (None, true) => {
+            println!("Replay failed: the replayed token was accepted but catreplay prohibits it");
+            false
+        }
+        (Some(status), false) => {
+            println!(
+                "Replay failed: the replayed token was rejected with {} but catreplay allows it",
+                status
+            );
+            false
+        }
+        (None, false) => {
+            println!("Replay passed: the replayed token was accepted");
+            true
+        }
+    };
+    Ok(passed)
+}
+
+async fn serve(args: ServeArgs) -> Result<bool, CatTesterError> {
+    let cdn = server::MockCdn::new(&args.key, args.segments, args.segment_duration)?
+        .with_country(args.country)
+        .with_geohash(args.geohash)
+        .with_verbose(args.verbose);
+    let listener = tokio::net::TcpListener::bind(&args.listen).await?;
+    println!(
+        "Serving http://{}/index.m3u8 with {} segments",
+        listener.local_addr()?,
+        args.segments
+    );
+    cdn.serve(listener).await?;
+    Ok(true)
 }
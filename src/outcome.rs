@@ -0,0 +1,221 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Why a request did not do what a CDN with working CAT support should do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    /// The response status was not 2xx
+    Status(u16),
+    /// The renewal deadline has passed and the response had no renewed token
+    MissingRenewal,
+    /// The request was sent with a token that had already expired
+    NotRenewedInTime,
+    /// The status differs from the one the scenario expects
+    UnexpectedStatus { expected: u16, actual: u16 },
+    /// No renewed token had been received by this request
+    NoRenewalBy(u32),
+    /// The renewed token could not be decoded or is not a renewal of the token sent
+    InvalidRenewal(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Status(status) => write!(f, "status {}", status),
+            Failure::MissingRenewal => write!(f, "no renewed token after the renewal deadline"),
+            Failure::NotRenewedInTime => write!(f, "token expired before it was renewed"),
+            Failure::UnexpectedStatus { expected, actual } => {
+                write!(f, "expected status {}, got {}", expected, actual)
+            }
+            Failure::NoRenewalBy(request) => {
+                write!(f, "no renewed token by request {}", request)
+            }
+            Failure::InvalidRenewal(reason) => write!(f, "invalid renewed token, {}", reason),
+        }
+    }
+}
+
+/// A redirect response that was followed, location is the Location header as sent
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redirect {
+    pub status: u16,
+    pub url: String,
+    pub location: String,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.status, self.url, self.location)
+    }
+}
+
+/// Result of one segment, init or key request
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub request: u32,
+    /// The url requested, before any redirect
+    pub url: String,
+    /// Status of the last response, after redirects were followed
+    pub status: u16,
+    /// The response carried a renewed token
+    pub renewed: bool,
+    /// Redirects followed to get the response, in order
+    pub redirects: Vec<Redirect>,
+    pub failures: Vec<Failure>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Every request made in a run, in the order they were sent
+#[derive(Debug, Default)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    pub fn failed(&self) -> impl Iterator<Item = &Outcome> {
+        self.outcomes.iter().filter(|o| !o.passed())
+    }
+
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(Outcome::passed)
+    }
+
+    /// Whether every token was renewed before it expired, None if the token
+    /// was neither renewed nor sent after it expired
+    pub fn renewed_in_time(&self) -> Option<bool> {
+        let expired = self
+            .outcomes
+            .iter()
+            .any(|o| o.failures.contains(&Failure::NotRenewedInTime));
+        let renewed = self.outcomes.iter().any(|o| o.renewed);
+        (expired || renewed).then_some(!expired)
+    }
+}
+
+// Tracks when the token in use must be renewed and when it expires. By
+// default the token created by the tester expires after two ttl and asks for
+// renewal after half a ttl, a renewed token gets ttl added to the time it
+// was renewed. Times are since the epoch, see Clock
+#[derive(Clone, Debug)]
+pub struct RenewalClock {
+    renew_after: Duration,
+    exp_add: Duration,
+    renew_by: Duration,
+    expires: Duration,
+}
+
+impl RenewalClock {
+    pub fn new(issued: Duration, ttl: u64) -> Self {
+        let ttl = Duration::from_secs(ttl);
+        Self::with_times(issued, ttl / 2, ttl, ttl * 2)
+    }
+
+    /// A token that should be renewed `renew_after` and expires
+    /// `expires_after` it was issued, a renewed token is valid for `exp_add`
+    pub fn with_times(
+        issued: Duration,
+        renew_after: Duration,
+        exp_add: Duration,
+        expires_after: Duration,
+    ) -> Self {
+        Self {
+            renew_after,
+            exp_add,
+            renew_by: issued + renew_after,
+            expires: issued + expires_after,
+        }
+    }
+
+    pub fn renewed(&mut self, at: Duration) {
+        self.renew_by = at + self.renew_after;
+        self.expires = at + self.exp_add;
+    }
+
+    /// Check a request sent at `sent` whose response arrived at `received`
+    pub fn check(&mut self, sent: Duration, received: Duration, renewed: bool) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        if sent >= self.expires {
+            failures.push(Failure::NotRenewedInTime);
+        }
+        if renewed {
+            self.renewed(received);
+        } else if received >= self.renew_by {
+            failures.push(Failure::MissingRenewal);
+        }
+        failures
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renewal_is_only_required_after_the_deadline() {
+        let issued = Duration::from_secs(1_700_000_000);
+        let mut clock = RenewalClock::new(issued, 20);
+        let early = issued + Duration::from_secs(5);
+        assert!(clock.check(early, early, false).is_empty());
+
+        let late = issued + Duration::from_secs(11);
+        assert_eq!(
+            clock.check(late, late, false),
+            vec![Failure::MissingRenewal]
+        );
+        assert!(clock.check(late, late, true).is_empty());
+
+        // the renewed token asks for renewal half a ttl after it was renewed
+        let next = late + Duration::from_secs(9);
+        assert!(clock.check(next, next, false).is_empty());
+    }
+
+    #[test]
+    fn expired_token_is_reported() {
+        let issued = Duration::from_secs(1_700_000_000);
+        let mut clock = RenewalClock::new(issued, 20);
+        let expired = issued + Duration::from_secs(40);
+        assert_eq!(
+            clock.check(expired, expired, false),
+            vec![Failure::NotRenewedInTime, Failure::MissingRenewal]
+        );
+
+        clock.renewed(expired);
+        let renewed_expired = expired + Duration::from_secs(20);
+        assert_eq!(
+            clock.check(renewed_expired, renewed_expired, true),
+            vec![Failure::NotRenewedInTime]
+        );
+    }
+
+    #[test]
+    fn report_collects_failed_requests() {
+        let outcome = |request, failures| Outcome {
+            request,
+            url: String::from("segment.ts"),
+            status: 200,
+            renewed: request == 2,
+            redirects: Vec::new(),
+            failures,
+        };
+        let report = Report {
+            outcomes: vec![outcome(1, vec![]), outcome(2, vec![Failure::Status(403)])],
+        };
+        assert!(!report.passed());
+        assert_eq!(
+            report.failed().map(|o| o.request).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(report.renewed_in_time(), Some(true));
+
+        let report = Report {
+            outcomes: vec![outcome(1, vec![Failure::NotRenewedInTime])],
+        };
+        assert_eq!(report.renewed_in_time(), Some(false));
+        assert_eq!(Report::default().renewed_in_time(), None);
+    }
+}
@@ -0,0 +1,563 @@
+// Parser for HLS playlists (RFC 8216), only the tags that matter when
+// fetching segments are kept, everything else is ignored.
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+}
+
+/// A #EXT-X-STREAM-INF entry
+#[derive(Debug, Default, PartialEq)]
+pub struct Variant {
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub resolution: Option<String>,
+    pub codecs: Option<String>,
+    pub uri: String,
+}
+
+/// A #EXT-X-MEDIA entry, the uri is missing when the media is muxed into the variant
+#[derive(Debug, Default, PartialEq)]
+pub struct Rendition {
+    pub media_type: String,
+    pub group_id: String,
+    pub name: String,
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MediaPlaylist {
+    pub version: Option<u64>,
+    pub target_duration: u64,
+    pub media_sequence: u64,
+    pub discontinuity_sequence: u64,
+    pub playlist_type: Option<String>,
+    pub end_list: bool,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Segment {
+    pub uri: String,
+    pub duration: f64,
+    pub title: Option<String>,
+    /// Media sequence number of the segment
+    pub sequence: u64,
+    pub byte_range: Option<ByteRange>,
+    /// Set if a #EXT-X-DISCONTINUITY comes before the segment
+    pub discontinuity: bool,
+    pub program_date_time: Option<String>,
+    /// The #EXT-X-MAP that applies to the segment
+    pub map: Option<Map>,
+    /// The #EXT-X-KEY tags that applies to the segment
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ByteRange {
+    pub length: u64,
+    pub offset: Option<u64>,
+}
+
+impl ByteRange {
+    /// Value for a Range request header
+    pub fn header_value(&self) -> String {
+        let start = self.offset.unwrap_or(0);
+        format!("bytes={}-{}", start, start + self.length.saturating_sub(1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Map {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Key {
+    pub method: String,
+    pub uri: Option<String>,
+    pub iv: Option<String>,
+    pub key_format: Option<String>,
+    pub key_id: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PlaylistError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl MediaPlaylist {
+    /// Total duration of all segments in seconds
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+}
+
+impl PlaylistError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for PlaylistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "playlist line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for PlaylistError {}
+
+pub fn parse(text: &str) -> Result<Playlist, PlaylistError> {
+    // keep the line numbers for error messages, blank lines carry no information
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    match lines.next() {
+        Some((_, "#EXTM3U")) => {}
+        Some((line, _)) => return Err(PlaylistError::new(line, "missing #EXTM3U header")),
+        None => return Err(PlaylistError::new(0, "empty playlist")),
+    }
+    let lines: Vec<(usize, &str)> = lines.collect();
+    if lines
+        .iter()
+        .any(|(_, line)| line.starts_with("#EXT-X-STREAM-INF"))
+    {
+        parse_master(&lines).map(Playlist::Master)
+    } else {
+        parse_media(&lines).map(Playlist::Media)
+    }
+}
+
+fn parse_master(lines: &[(usize, &str)]) -> Result<MasterPlaylist, PlaylistError> {
+    let mut playlist = MasterPlaylist::default();
+    let mut pending: Option<(usize, Variant)> = None;
+    for &(number, line) in lines {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let bandwidth = attribute_value(attributes, "BANDWIDTH")
+                .ok_or_else(|| PlaylistError::new(number, "#EXT-X-STREAM-INF without BANDWIDTH"))?;
+            let variant = Variant {
+                bandwidth: parse_number(number, "BANDWIDTH", &bandwidth)?,
+                average_bandwidth: attribute_value(attributes, "AVERAGE-BANDWIDTH")
+                    .map(|b| parse_number(number, "AVERAGE-BANDWIDTH", &b))
+                    .transpose()?,
+                resolution: attribute_value(attributes, "RESOLUTION"),
+                codecs: attribute_value(attributes, "CODECS"),
+                uri: String::new(),
+            };
+            pending = Some((number, variant));
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MEDIA:") {
+            playlist.renditions.push(Rendition {
+                media_type: attribute_value(attributes, "TYPE").unwrap_or_default(),
+                group_id: attribute_value(attributes, "GROUP-ID").unwrap_or_default(),
+                name: attribute_value(attributes, "NAME").unwrap_or_default(),
+                uri: attribute_value(attributes, "URI"),
+            });
+        } else if !line.starts_with('#')
+            && let Some((_, mut variant)) = pending.take()
+        {
+            variant.uri = line.to_string();
+            playlist.variants.push(variant);
+        }
+    }
+    if let Some((number, _)) = pending {
+        return Err(PlaylistError::new(number, "#EXT-X-STREAM-INF without uri"));
+    }
+    Ok(playlist)
+}
+
+fn parse_media(lines: &[(usize, &str)]) -> Result<MediaPlaylist, PlaylistError> {
+    let mut playlist = MediaPlaylist::default();
+    let mut segment = Segment::default();
+    let mut has_extinf = false;
+    let mut map: Option<Map> = None;
+    let mut keys: Vec<Key> = Vec::new();
+    // consecutive #EXT-X-KEY tags belongs together, the next one after a
+    // segment replaces the whole set
+    let mut keys_done = false;
+    for &(number, line) in lines {
+        if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let (duration, title) = match value.split_once(',') {
+                Some((duration, title)) => (duration, title.trim()),
+                None => (value, ""),
+            };
+            segment.duration = duration.trim().parse().map_err(|_| {
+                PlaylistError::new(number, format!("invalid duration {}", duration))
+            })?;
+            segment.title = (!title.is_empty()).then(|| title.to_string());
+            has_extinf = true;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-VERSION:") {
+            playlist.version = Some(parse_number(number, "EXT-X-VERSION", value)?);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            playlist.target_duration = parse_number(number, "EXT-X-TARGETDURATION", value)?;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            playlist.media_sequence = parse_number(number, "EXT-X-MEDIA-SEQUENCE", value)?;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-DISCONTINUITY-SEQUENCE:") {
+            playlist.discontinuity_sequence =
+                parse_number(number, "EXT-X-DISCONTINUITY-SEQUENCE", value)?;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-PLAYLIST-TYPE:") {
+            playlist.playlist_type = Some(value.trim().to_string());
+        } else if line == "#EXT-X-ENDLIST" {
+            playlist.end_list = true;
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            segment.discontinuity = true;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            segment.program_date_time = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            segment.byte_range = Some(parse_byte_range(number, value)?);
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MAP:") {
+            let uri = attribute_value(attributes, "URI")
+                .ok_or_else(|| PlaylistError::new(number, "#EXT-X-MAP without URI"))?;
+            let byte_range = attribute_value(attributes, "BYTERANGE")
+                .map(|range| parse_byte_range(number, &range))
+                .transpose()?;
+            map = Some(Map { uri, byte_range });
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-KEY:") {
+            let method = attribute_value(attributes, "METHOD")
+                .ok_or_else(|| PlaylistError::new(number, "#EXT-X-KEY without METHOD"))?;
+            if keys_done {
+                keys.clear();
+                keys_done = false;
+            }
+            if method == "NONE" {
+                keys.clear();
+            } else {
+                keys.push(Key {
+                    method,
+                    uri: attribute_value(attributes, "URI"),
+                    iv: attribute_value(attributes, "IV"),
+                    key_format: attribute_value(attributes, "KEYFORMAT"),
+                    key_id: attribute_value(attributes, "KEYID"),
+                });
+            }
+        } else if line.starts_with('#') {
+            // unknown tags and comments
+            continue;
+        } else {
+            if !has_extinf {
+                return Err(PlaylistError::new(number, "segment uri without #EXTINF"));
+            }
+            segment.uri = line.to_string();
+            segment.sequence = playlist.media_sequence + playlist.segments.len() as u64;
+            // a range without offset starts where the previous range of the same uri ended
+            if let Some(range) = segment.byte_range.as_mut()
+                && range.offset.is_none()
+            {
+                range.offset = Some(match playlist.segments.last() {
+                    Some(Segment {
+                        uri,
+                        byte_range:
+                            Some(ByteRange {
+                                length,
+                                offset: Some(offset),
+                            }),
+                        ..
+                    }) if *uri == segment.uri => offset + length,
+                    _ => 0,
+                });
+            }
+            segment.map = map.clone();
+            segment.keys = keys.clone();
+            keys_done = true;
+            has_extinf = false;
+            playlist.segments.push(std::mem::take(&mut segment));
+        }
+    }
+    Ok(playlist)
+}
+
+fn parse_number<T: std::str::FromStr>(
+    line: usize,
+    name: &str,
+    value: &str,
+) -> Result<T, PlaylistError> {
+    // USP puts a comment after the version, "#EXT-X-VERSION:6 ## Created with ..."
+    let number = value.split_whitespace().next().unwrap_or_default();
+    number
+        .parse()
+        .map_err(|_| PlaylistError::new(line, format!("invalid {} value {}", name, value)))
+}
+
+// <length>[@<offset>]
+fn parse_byte_range(line: usize, value: &str) -> Result<ByteRange, PlaylistError> {
+    let (length, offset) = match value.split_once('@') {
+        Some((length, offset)) => (length, Some(offset)),
+        None => (value, None),
+    };
+    Ok(ByteRange {
+        length: parse_number(line, "BYTERANGE", length)?,
+        offset: offset
+            .map(|o| parse_number(line, "BYTERANGE", o))
+            .transpose()?,
+    })
+}
+
+// Get the value of NAME=value from an attribute list, quoted values may contain commas
+fn attribute_value(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    while !rest.is_empty() {
+        let (key, value_start) = rest.split_once('=')?;
+        let (value, next) = if let Some(quoted) = value_start.strip_prefix('"') {
+            let end = quoted.find('"')?;
+            (&quoted[..end], quoted[end + 1..].trim_start_matches(','))
+        } else {
+            match value_start.split_once(',') {
+                Some((value, next)) => (value, next),
+                None => (value_start, ""),
+            }
+        };
+        if key.trim() == name {
+            return Some(value.to_string());
+        }
+        rest = next;
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Manifest from the README, as returned by Unified Streaming
+    const USP_MEDIA_PLAYLIST: &str = r#"
+#EXTM3U
+#EXTM3U
+#EXT-X-VERSION:6 ## Created with Unified Streaming Platform (version=1.13.0-29687)
+#EXT-X-MEDIA-SEQUENCE:455767831
+#EXT-X-INDEPENDENT-SEGMENTS
+#EXT-X-TARGETDURATION:6
+#USP-X-TIMESTAMP-MAP:MPEGTS=8322344688,LOCAL=2025-06-17T08:21:08.040000Z
+#EXT-X-MAP:URI="hls/5245-video=5000000.m4s"
+#EXT-X-PROGRAM-DATE-TIME:2025-06-17T08:21:08.040000Z
+#EXT-X-KEY:METHOD=SAMPLE-AES,KEYID=0x706fe4f5a1fc3ad2af49a6698b822bad,URI="data:text/plain;base64,AAAAdXBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAAFUSEHBv5PWh/DrSr0mmaYuCK60aCGNhc3RsYWJzIihleUpoYzNObGRFbGtJam9pZEhadFpXUnBZUzB5TURRMk5UY3lNU0o5MgdkZWZhdWx0SPPGiZsG",KEYFORMAT="urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed",KEYFORMATVERSIONS="1"
+#EXT-X-KEY:METHOD=SAMPLE-AES,URI="data:text/plain;charset=UTF-16;base64,KgMAAAEAAQAgAzwAVwBSAE0ASABFAEEARABFAFIAIAB2AGUAcgBzAGkAbwBuAD0AIgA0AC4AMwAuADAALgAwACIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAFMAPgA8AEsASQBEACAAVgBBAEwAVQBFAD0AIgA5AGUAUgB2AGMAUAB5AGgAMABqAHEAdgBTAGEAWgBwAGkANABJAHIAcgBRAD0APQAiACAAQQBMAEcASQBEAD0AIgBBAEUAUwBDAEIAQwAiACAALwA+ADwALwBLAEkARABTAD4APAAvAFAAUgBPAFQARQBDAFQASQBOAEYATwA+ADwATABBAF8AVQBSAEwAPgBoAHQAdABwAHMAOgAvAC8AbABpAGMALgBkAHIAbQB0AG8AZABhAHkALgBjAG8AbQAvAGwAaQBjAGUAbgBzAGUALQBwAHIAbwB4AHkALQBoAGUAYQBkAGUAcgBhAHUAdABoAC8AZAByAG0AdABvAGQAYQB5AC8AUgBpAGcAaAB0AHMATQBhAG4AYQBnAGUAcgAuAGEAcwBtAHgAPAAvAEwAQQBfAFUAUgBMAD4APABMAFUASQBfAFUAUgBMAD4AaAB0AHQAcABzADoALwAvAHAAbABhAHkAcgBlAGEAZAB5AC0AdQBpAC4AZQB4AGEAbQBwAGwAZQAuAGMAbwBtADwALwBMAFUASQBfAFUAUgBMAD4APABEAEUAQwBSAFkAUABUAE8AUgBTAEUAVABVAFAAPgBPAE4ARABFAE0AQQBOAEQAPAAvAEQARQBDAFIAWQBQAFQATwBSAFMARQBUAFUAUAA+ADwALwBEAEEAVABBAD4APAAvAFcAUgBNAEgARQBBAEQARQBSAD4A",KEYFORMAT="com.microsoft.playready",KEYFORMATVERSIONS="1"
+#EXT-X-KEY:METHOD=SAMPLE-AES,URI="skd://drmtoday?assetId=media-20465721&variantId&keyId=706fe4f5a1fc3ad2af49a6698b822bad",KEYFORMAT="com.apple.streamingkeydelivery",KEYFORMATVERSIONS="1"
+#EXT-X-KEY:METHOD=SAMPLE-AES,KEYID=0x706fe4f5a1fc3ad2af49a6698b822bad,URI="data:text/plain;base64,AAAAn3Bzc2gAAAAAPV5tNZuaQei4Q908bnLELAAAAH97InZlcnNpb24iOiJWMS4wIiwia2lkcyI6WyJjRy9rOWFIOE90S3ZTYVpwaTRJcnJRPT0iXSwiY29udGVudElEIjoiZXlKaGMzTmxkRWxrSWpvaWRIWnRaV1JwWVMweU1EUTJOVGN5TVNKOSIsImVuc2NoZW1hIjoiY2JjcyJ9",IV=0xE105A618D09DC0CCFFCDBCCA711E6BD0,KEYFORMAT="urn:uuid:3d5e6d35-9b9a-41e8-b843-dd3c6e72c42c",KEYFORMATVERSIONS="1"
+#EXTINF:3.84, no desc
+hls/5245-video=5000000-455767831.m4s
+#EXTINF:3.84, no desc
+hls/5245-video=5000000-455767832.m4s
+"#;
+
+    fn media(text: &str) -> MediaPlaylist {
+        match parse(text).unwrap() {
+            Playlist::Media(playlist) => playlist,
+            Playlist::Master(_) => panic!("expected a media playlist"),
+        }
+    }
+
+    #[test]
+    fn parse_usp_media_playlist() {
+        let playlist = media(USP_MEDIA_PLAYLIST);
+        assert_eq!(playlist.version, Some(6));
+        assert_eq!(playlist.target_duration, 6);
+        assert_eq!(playlist.media_sequence, 455767831);
+        assert!(!playlist.end_list);
+        assert_eq!(playlist.segments.len(), 2);
+
+        let first = &playlist.segments[0];
+        assert_eq!(first.uri, "hls/5245-video=5000000-455767831.m4s");
+        assert_eq!(first.duration, 3.84);
+        assert_eq!(first.title.as_deref(), Some("no desc"));
+        assert_eq!(first.sequence, 455767831);
+        assert_eq!(
+            first.program_date_time.as_deref(),
+            Some("2025-06-17T08:21:08.040000Z")
+        );
+        assert_eq!(
+            first.map,
+            Some(Map {
+                uri: String::from("hls/5245-video=5000000.m4s"),
+                byte_range: None
+            })
+        );
+        assert_eq!(first.keys.len(), 4);
+        assert_eq!(
+            first.keys[1].key_format.as_deref(),
+            Some("com.microsoft.playready")
+        );
+        assert!(
+            first.keys[1]
+                .uri
+                .as_deref()
+                .unwrap()
+                .starts_with("data:text/plain;charset=UTF-16;base64,")
+        );
+        assert_eq!(
+            first.keys[2].key_format.as_deref(),
+            Some("com.apple.streamingkeydelivery")
+        );
+        assert!(first.keys[2].uri.as_deref().unwrap().starts_with("skd://"));
+        assert_eq!(
+            first.keys[3].iv.as_deref(),
+            Some("0xE105A618D09DC0CCFFCDBCCA711E6BD0")
+        );
+
+        let second = &playlist.segments[1];
+        assert_eq!(second.sequence, 455767832);
+        assert_eq!(second.program_date_time, None);
+        assert_eq!(second.map, first.map);
+        assert_eq!(second.keys, first.keys);
+    }
+
+    #[test]
+    fn parse_indented_playlist_with_blank_lines() {
+        let body = r#"
+        #EXTM3U
+        #EXT-X-TARGETDURATION:6
+        ## comment
+        #EXTINF:3.84, no desc
+
+        hls/20465721-video=5000000-455767831.m4s
+        #EXTINF:3.84, no desc
+        hls/20465721-video=5000000-455767832.m4s
+        "#;
+        let playlist = media(body);
+        assert_eq!(
+            playlist.segments[0].uri,
+            "hls/20465721-video=5000000-455767831.m4s"
+        );
+        assert_eq!(playlist.segments.len(), 2);
+    }
+
+    #[test]
+    fn parse_vod_playlist_with_byte_ranges_and_discontinuity() {
+        let body = "#EXTM3U
+#EXT-X-PLAYLIST-TYPE:VOD
+#EXT-X-TARGETDURATION:10
+#EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"
+#EXT-X-KEY:METHOD=AES-128,URI=\"https://keys.example.com/key1\"
+#EXTINF:10.0,
+#EXT-X-BYTERANGE:1000@720
+main.mp4
+#EXT-X-DISCONTINUITY
+#EXT-X-KEY:METHOD=NONE
+#EXTINF:9.5,
+#EXT-X-BYTERANGE:500
+main.mp4
+#EXT-X-ENDLIST";
+        let playlist = media(body);
+        assert_eq!(playlist.playlist_type.as_deref(), Some("VOD"));
+        assert!(playlist.end_list);
+        assert_eq!(playlist.duration(), 19.5);
+        let first = &playlist.segments[0];
+        assert_eq!(
+            first.map.as_ref().unwrap().byte_range,
+            Some(ByteRange {
+                length: 720,
+                offset: Some(0)
+            })
+        );
+        assert_eq!(
+            first.byte_range,
+            Some(ByteRange {
+                length: 1000,
+                offset: Some(720)
+            })
+        );
+        assert_eq!(
+            first.byte_range.as_ref().unwrap().header_value(),
+            "bytes=720-1719"
+        );
+        assert_eq!(first.keys[0].method, "AES-128");
+        assert!(!first.discontinuity);
+        let second = &playlist.segments[1];
+        assert!(second.discontinuity);
+        assert!(second.keys.is_empty());
+        assert_eq!(
+            second.byte_range,
+            Some(ByteRange {
+                length: 500,
+                offset: Some(1720)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_master_playlist() {
+        let body = r#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",URI="5245-audio_eng=128000.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,AVERAGE-BANDWIDTH=2200000,RESOLUTION=1280x720,CODECS="avc1.4d401f,mp4a.40.2",AUDIO="aac"
+5245-video=2499968.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS="avc1.4d401e,mp4a.40.2"
+
+5245-video=799968.m3u8
+"#;
+        let Playlist::Master(playlist) = parse(body).unwrap() else {
+            panic!("expected a master playlist");
+        };
+        assert_eq!(playlist.variants.len(), 2);
+        assert_eq!(
+            playlist.variants[0],
+            Variant {
+                bandwidth: 2500000,
+                average_bandwidth: Some(2200000),
+                resolution: Some(String::from("1280x720")),
+                codecs: Some(String::from("avc1.4d401f,mp4a.40.2")),
+                uri: String::from("5245-video=2499968.m3u8"),
+            }
+        );
+        assert_eq!(playlist.variants[1].uri, "5245-video=799968.m3u8");
+        assert_eq!(playlist.renditions[0].media_type, "AUDIO");
+        assert_eq!(
+            playlist.renditions[0].uri.as_deref(),
+            Some("5245-audio_eng=128000.m3u8")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("").unwrap_err().message, "empty playlist");
+        assert_eq!(parse("\n  \n").unwrap_err().message, "empty playlist");
+        assert_eq!(
+            parse("<html>403</html>").unwrap_err(),
+            PlaylistError::new(1, "missing #EXTM3U header")
+        );
+        assert_eq!(
+            parse("#EXTM3U\n#EXTINF:abc,\nsegment.ts").unwrap_err(),
+            PlaylistError::new(2, "invalid duration abc")
+        );
+        assert_eq!(
+            parse("#EXTM3U\nsegment.ts").unwrap_err(),
+            PlaylistError::new(2, "segment uri without #EXTINF")
+        );
+        assert_eq!(
+            parse("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1").unwrap_err(),
+            PlaylistError::new(2, "#EXT-X-STREAM-INF without uri")
+        );
+    }
+
+    #[test]
+    fn playlist_ending_with_tag_has_no_segments() {
+        let playlist = media("#EXTM3U\n#EXT-X-TARGETDURATION:6");
+        assert!(playlist.segments.is_empty());
+    }
+
+    #[test]
+    fn attribute_list_values() {
+        let attributes = r#"BANDWIDTH=800000,CODECS="avc1.4d401e,mp4a.40.2",RESOLUTION=640x360"#;
+        assert_eq!(
+            attribute_value(attributes, "CODECS").as_deref(),
+            Some("avc1.4d401e,mp4a.40.2")
+        );
+        assert_eq!(
+            attribute_value(attributes, "RESOLUTION").as_deref(),
+            Some("640x360")
+        );
+        assert_eq!(attribute_value(attributes, "AUDIO"), None);
+    }
+}
@@ -0,0 +1,1423 @@
+use crate::clock::Clock;
+use crate::cose;
+use crate::error::CatTesterError;
+use crate::inspect::{self, CAT_CLAIMS};
+use crate::token;
+use crate::verify::{self, CheckStatus, ClaimCheck, RequestInfo};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use common_access_token::{CborValue, Token, cat_keys, renewal_params, renewal_types};
+use http_body_util::Full;
+use hyper::StatusCode;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use reqwest::Url;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+/// Name of the token header and cookie
+const TOKEN_NAME: &str = "CTA-Common-Access-Token";
+/// Query parameter with the token, see TokenType::CookieAsQuery
+const TOKEN_QUERY: &str = "CAT";
+/// Replaced by the CATIFDATA claim in the headers of a CATIF response
+const CATIFDATA_PLACEHOLDER: &str = "{catifdata}";
+
+/// Where the token was found in a request
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transport {
+    Header,
+    Cookie,
+    Query,
+}
+
+/// Request as read from the connection, the body is not kept
+#[derive(Debug)]
+struct Request {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl<B> From<&hyper::Request<B>> for Request {
+    fn from(request: &hyper::Request<B>) -> Self {
+        Self {
+            method: request.method().to_string(),
+            target: request
+                .uri()
+                .path_and_query()
+                .map_or_else(|| String::from("/"), |target| target.to_string()),
+            headers: request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn cookie(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case("cookie"))
+            .flat_map(|(_, value)| value.split(';'))
+            .filter_map(|cookie| cookie.split_once('='))
+            .find(|(n, _)| n.trim() == name)
+            .map(|(_, value)| value.trim())
+    }
+
+    fn url(&self) -> Option<Url> {
+        let host = self.header("host").unwrap_or("localhost");
+        Url::parse(&format!("http://{}{}", host, self.target)).ok()
+    }
+
+    // Every token in the request and where it was sent
+    fn tokens(&self) -> Vec<(Transport, String)> {
+        let mut tokens = Vec::new();
+        if let Some(token) = self.header(TOKEN_NAME) {
+            tokens.push((Transport::Header, token.to_string()));
+        }
+        if let Some(token) = self.cookie(TOKEN_NAME) {
+            tokens.push((Transport::Cookie, token.to_string()));
+        }
+        if let Some(url) = self.url()
+            && let Some((_, token)) = url.query_pairs().find(|(name, _)| name == TOKEN_QUERY)
+        {
+            tokens.push((Transport::Query, token.into_owned()));
+        }
+        tokens
+    }
+
+    // A request can carry the token it started with next to a renewed one,
+    // in the renewal header or cookie of its CATR claim or in the query after
+    // a redirect renewal. The newest token is used, the query wins a tie.
+    // The token comes with its bytes, the tag is checked on them
+    fn token(&self) -> Option<Result<Received, CatTesterError>> {
+        let mut tokens = Vec::new();
+        let mut error = None;
+        for (transport, encoded) in self.tokens() {
+            match decode(&encoded) {
+                Ok((token, bytes)) => tokens.push((transport, token, bytes)),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        let renewals: Vec<Received> = tokens
+            .iter()
+            .filter_map(
+                |(_, token, _)| match token.claims.custom.get(&cat_keys::CATR) {
+                    Some(CborValue::Map(catr)) => Some(catr),
+                    _ => None,
+                },
+            )
+            .flat_map(|catr| {
+                let header = text(catr.get(&renewal_params::HEADER_NAME))
+                    .filter(|name| !name.eq_ignore_ascii_case(TOKEN_NAME))
+                    .and_then(|name| self.header(name))
+                    .map(|token| (Transport::Header, token));
+                let cookie = text(catr.get(&renewal_params::COOKIE_NAME))
+                    .filter(|name| *name != TOKEN_NAME)
+                    .and_then(|name| self.cookie(name))
+                    .map(|token| (Transport::Cookie, token));
+                header.into_iter().chain(cookie)
+            })
+            .filter_map(|(transport, encoded)| {
+                let (token, bytes) = decode(encoded).ok()?;
+                Some((transport, token, bytes))
+            })
+            .collect();
+        tokens.extend(renewals);
+        tokens.sort_by_key(|(transport, _, _)| *transport == Transport::Query);
+        match tokens
+            .into_iter()
+            .max_by_key(|(_, token, _)| token.claims.registered.iat)
+        {
+            Some(token) => Some(Ok(token)),
+            None => error.map(Err),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: vec![(String::from("Content-Type"), content_type.to_string())],
+            body: body.into(),
+        }
+    }
+
+    fn rejected(status: u16, reason: &str) -> Self {
+        Self::new(status, "text/plain", format!("{}\n", reason))
+    }
+
+    // Headers that are not valid in HTTP are left out
+    fn into_hyper(self) -> hyper::Response<Full<Bytes>> {
+        let mut response = hyper::Response::new(Full::new(Bytes::from(self.body)));
+        *response.status_mut() =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                response.headers_mut().append(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// A CDN that only serves a playlist and its segments to requests with a
+/// valid token and renews the token the way its CATR claim asks for
+pub struct MockCdn {
+    key: token::Key,
+    segments: u32,
+    segment_duration: u64,
+    // where every client is pretended to be, for the geo claims
+    country: Option<String>,
+    geohash: Option<String>,
+    // cti of the tokens with a catreplay claim that were used
+    used: Mutex<HashSet<Vec<u8>>>,
+    // log every request and every replayed token
+    verbose: bool,
+    clock: Clock,
+}
+
+impl MockCdn {
+    pub fn new(key: &str, segments: u32, segment_duration: u64) -> Result<Self, CatTesterError> {
+        Ok(Self {
+            key: token::load_key(key)?,
+            segments,
+            segment_duration,
+            country: None,
+            geohash: None,
+            used: Mutex::new(HashSet::new()),
+            verbose: false,
+            clock: Clock::default(),
+        })
+    }
+
+    /// Pretend every client is in this ISO 3166 country or subdivision
+    pub fn with_country(self, country: Option<String>) -> Self {
+        Self { country, ..self }
+    }
+
+    /// Pretend every client is at this geohash
+    pub fn with_geohash(self, geohash: Option<String>) -> Self {
+        Self { geohash, ..self }
+    }
+
+    /// Log every request with its status and every token that is used again
+    pub fn with_verbose(self, verbose: bool) -> Self {
+        Self { verbose, ..self }
+    }
+
+    /// Clock the tokens are checked and renewed at
+    #[cfg(test)]
+    pub fn with_clock(self, clock: Clock) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Serve connections until the process is stopped
+    pub async fn serve(self, listener: TcpListener) -> Result<(), CatTesterError> {
+        let cdn = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let cdn = cdn.clone();
+            tokio::spawn(async move {
+                let service = service_fn(|request| {
+                    let response = cdn.handle(&request, peer.ip());
+                    async move { Ok::<_, Infallible>(response) }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    eprintln!("Connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    // The body of the request is not read
+    fn handle(
+        &self,
+        request: &hyper::Request<Incoming>,
+        client_ip: IpAddr,
+    ) -> hyper::Response<Full<Bytes>> {
+        let request = Request::from(request);
+        let response = self.respond(&request, client_ip, self.clock.timestamp());
+        if self.verbose {
+            eprintln!(
+                "{} {} {} -> {}",
+                client_ip, request.method, request.target, response.status
+            );
+        }
+        response.into_hyper()
+    }
+
+    fn respond(&self, request: &Request, client_ip: IpAddr, now: u64) -> Response {
+        let Some(url) = request.url() else {
+            return Response::rejected(404, "invalid request target");
+        };
+        // a browser sends the preflight without the token
+        if request.method == "OPTIONS" {
+            return preflight(request);
+        }
+        let (transport, token, bytes) = match request.token() {
+            Some(Ok(token)) => token,
+            Some(Err(e)) => return Response::rejected(401, &e.to_string()),
+            None => return Response::rejected(401, "no token"),
+        };
+        if let Err(e) = cose::verify(&bytes, &self.key) {
+            return Response::rejected(401, &format!("mac is not valid: {}", e));
+        }
+        let info = RequestInfo {
+            url: Some(url.clone()),
+            method: Some(request.method.clone()),
+            client_ip: Some(client_ip),
+            country: self.country.clone(),
+            geohash: self.geohash.clone(),
+            headers: Some(request.headers.clone()),
+        };
+        let failed: Vec<ClaimCheck> = verify::check_claims(&token, &info, now)
+            .into_iter()
+            .filter(|c| c.status == CheckStatus::Fail)
+            .collect();
+        if !failed.is_empty() {
+            let reasons: Vec<String> = failed.iter().map(ClaimCheck::to_string).collect();
+            if let Some(response) = conditional(&token, &failed, &reasons) {
+                return response;
+            }
+            // an expired token is not authorized, a valid token that does
+            // not allow this request is forbidden
+            let status = if failed.iter().any(|c| c.claim == "exp" || c.claim == "nbf") {
+                401
+            } else {
+                403
+            };
+            return Response::rejected(status, &reasons.join("\n"));
+        }
+
+        if let Some(CborValue::Integer(replay)) = token.claims.custom.get(&cat_keys::CATREPLAY)
+            && *replay != 0
+        {
+            // a token without cti can not be told apart from other tokens
+            match token.claims.registered.cti.clone() {
+                Some(cti) => {
+                    let first_use = self
+                        .used
+                        .lock()
+                        .map(|mut used| used.insert(cti))
+                        .unwrap_or(true);
+                    if !first_use {
+                        if *replay == 1 {
+                            return Response::rejected(401, "token was already used");
+                        }
+                        if self.verbose {
+                            eprintln!("Token {} was used again", hex::encode(&token.signature));
+                        }
+                    }
+                }
+                None if *replay == 1 => {
+                    return Response::rejected(
+                        401,
+                        "catreplay prohibits reuse but there is no cti",
+                    );
+                }
+                None => {}
+            }
+        }
+
+        match self.redirect_renewal(&token, &url, now) {
+            Ok(Some(response)) => return response,
+            Ok(None) => {}
+            Err(e) => eprintln!("Renewal failed: {}", e),
+        }
+        let mut response = match self.content(url.path()) {
+            Some(response) => response,
+            None => return Response::rejected(404, "not found"),
+        };
+        match self.renewal(&token, transport, now) {
+            Ok(Some(header)) => response.headers.push(header),
+            Ok(None) => {}
+            Err(e) => eprintln!("Renewal failed: {}", e),
+        }
+        response
+    }
+
+    // Playlist at any .m3u8 path and its segments next to it
+    fn content(&self, path: &str) -> Option<Response> {
+        let filename = path.rsplit('/').next().unwrap_or_default();
+        if filename.ends_with(".m3u8") {
+            return Some(Response::new(
+                200,
+                "application/vnd.apple.mpegurl",
+                self.playlist(),
+            ));
+        }
+        let number: u32 = filename
+            .strip_prefix("segment_")?
+            .strip_suffix(".ts")?
+            .parse()
+            .ok()?;
+        (number < self.segments).then(|| {
+            Response::new(
+                200,
+                "video/mp2t",
+                format!("segment {}\n", number).into_bytes(),
+            )
+        })
+    }
+
+    fn playlist(&self) -> String {
+        let mut playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n",
+            self.segment_duration
+        );
+        for number in 0..self.segments {
+            playlist.push_str(&format!(
+                "#EXTINF:{}.0,\nsegment_{}.ts\n",
+                self.segment_duration, number
+            ));
+        }
+        playlist.push_str("#EXT-X-ENDLIST\n");
+        playlist
+    }
+
+    // Header with the renewed token once the CATR deadline has passed, a
+    // Set-Cookie for cookie renewal and for automatic renewal of a token sent
+    // as cookie or query. A token in the query is moved into a cookie right
+    // away, it is how a player that can only add a query gets a cookie
+    fn renewal(
+        &self,
+        token: &Token,
+        transport: Transport,
+        now: u64,
+    ) -> Result<Option<(String, String)>, CatTesterError> {
+        let Some(CborValue::Map(catr)) = token.claims.custom.get(&cat_keys::CATR) else {
+            return Ok(None);
+        };
+        let kind = match catr.get(&renewal_params::TYPE) {
+            Some(CborValue::Integer(kind)) => *kind,
+            _ => renewal_types::AUTOMATIC,
+        };
+        let as_cookie = match kind {
+            renewal_types::COOKIE => true,
+            renewal_types::HEADER => false,
+            renewal_types::AUTOMATIC => transport != Transport::Header,
+            _ => return Ok(None),
+        };
+        let moved = as_cookie && transport == Transport::Query;
+        if !moved && deadline_passed(catr, now) == Some(false) {
+            return Ok(None);
+        }
+        let Some(renewed) = token::renew(token, &self.key, now)? else {
+            return Ok(None);
+        };
+        let encoded = URL_SAFE_NO_PAD.encode(renewed);
+        if as_cookie {
+            let name = text(catr.get(&renewal_params::COOKIE_NAME)).unwrap_or(TOKEN_NAME);
+            let mut cookie = format!("{}={}", name, encoded);
+            // there is no tls, a Secure cookie would not be sent back
+            for param in texts(catr.get(&renewal_params::COOKIE_PARAMS)) {
+                if !param.eq_ignore_ascii_case("secure") {
+                    cookie.push_str("; ");
+                    cookie.push_str(param);
+                }
+            }
+            Ok(Some((String::from("Set-Cookie"), cookie)))
+        } else {
+            let name = text(catr.get(&renewal_params::HEADER_NAME)).unwrap_or(TOKEN_NAME);
+            let mut value = encoded;
+            for param in texts(catr.get(&renewal_params::HEADER_PARAMS)) {
+                value.push_str("; ");
+                value.push_str(param);
+            }
+            Ok(Some((name.to_string(), value)))
+        }
+    }
+
+    // Redirect to the same url with a renewed token in the query once the
+    // deadline of a token with redirect renewal has passed. Without a
+    // deadline every response would be a redirect so there is no renewal,
+    // and a token issued this second is not renewed again
+    fn redirect_renewal(
+        &self,
+        token: &Token,
+        url: &Url,
+        now: u64,
+    ) -> Result<Option<Response>, CatTesterError> {
+        let Some(CborValue::Map(catr)) = token.claims.custom.get(&cat_keys::CATR) else {
+            return Ok(None);
+        };
+        if catr.get(&renewal_params::TYPE) != Some(&CborValue::Integer(renewal_types::REDIRECT)) {
+            return Ok(None);
+        }
+        if deadline_passed(catr, now) != Some(true) || token.claims.registered.iat >= Some(now) {
+            return Ok(None);
+        }
+        let Some(renewed) = token::renew(token, &self.key, now)? else {
+            return Ok(None);
+        };
+        let encoded = URL_SAFE_NO_PAD.encode(renewed);
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| name != TOKEN_QUERY)
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        let mut location = url.clone();
+        location
+            .query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair(TOKEN_QUERY, &encoded);
+        let status = match catr.get(&renewal_params::CODE) {
+            Some(CborValue::Integer(code)) => u16::try_from(*code).unwrap_or(302),
+            _ => 302,
+        };
+        let mut response = Response::new(status, "text/plain", Vec::new());
+        response
+            .headers
+            .push((String::from("Location"), location.to_string()));
+        Ok(Some(response))
+    }
+}
+
+// Whether the CATR deadline has passed, None if there is no deadline
+fn deadline_passed(catr: &BTreeMap<i32, CborValue>, now: u64) -> Option<bool> {
+    match catr.get(&renewal_params::DEADLINE) {
+        Some(CborValue::Integer(deadline)) => Some(now as i64 >= *deadline),
+        _ => None,
+    }
+}
+
+// A token, where it was sent and its bytes
+type Received = (Transport, Token, Vec<u8>);
+
+// A base64url token and its bytes
+fn decode(encoded: &str) -> Result<(Token, Vec<u8>), CatTesterError> {
+    let bytes = inspect::token_bytes(encoded)?;
+    Ok((cose::open(&bytes)?, bytes))
+}
+
+// The CATIF response for the first failed claim that has one, with
+// {catifdata} in the header values replaced by the CATIFDATA claim
+fn conditional(token: &Token, failed: &[ClaimCheck], reasons: &[String]) -> Option<Response> {
+    let Some(CborValue::Bytes(catif)) = token.claims.custom.get(&cat_keys::CATIF) else {
+        return None;
+    };
+    let responses = cose::entries(catif).ok()?;
+    let (status, headers) = failed
+        .iter()
+        .filter_map(|check| claim_key(check.claim))
+        .find_map(|key| {
+            let (_, response) = responses.iter().find(|(claim, _)| *claim == key)?;
+            catif_response(response)
+        })?;
+    let data = text(token.claims.custom.get(&cat_keys::CATIFDATA)).unwrap_or_default();
+    let mut response = Response::rejected(status, &reasons.join("\n"));
+    for (name, value) in headers {
+        response
+            .headers
+            .push((name.to_string(), value.replace(CATIFDATA_PLACEHOLDER, data)));
+    }
+    Some(response)
+}
+
+// The status and headers of a CATIF response, [status, {name: value}] where
+// a value is a text or an array of texts
+fn catif_response(response: &[u8]) -> Option<(u16, Vec<(&str, String)>)> {
+    let items = cose::items(response).ok()?;
+    let [status, headers] = items.as_slice() else {
+        return None;
+    };
+    let CborValue::Integer(status) = cose::value(status).ok()? else {
+        return None;
+    };
+    let mut pairs = Vec::new();
+    for (name, value) in cose::text_entries(headers).ok()? {
+        match cose::value(value).ok()? {
+            CborValue::Text(value) => pairs.push((name, value)),
+            CborValue::Array(values) => {
+                for value in values {
+                    if let CborValue::Text(value) = value {
+                        pairs.push((name, value));
+                    }
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some((u16::try_from(status).ok()?, pairs))
+}
+
+// The key of the claim a check is for, crit is a header and has none
+fn claim_key(claim: &str) -> Option<i64> {
+    match claim {
+        "exp" => Some(i64::from(cose::EXP)),
+        "nbf" => Some(i64::from(cose::NBF)),
+        name => inspect::key_of(&CAT_CLAIMS, name).map(i64::from),
+    }
+}
+
+// Allow any origin to send the token header, a real CDN would check the origin
+fn preflight(request: &Request) -> Response {
+    let mut response = Response::new(204, "text/plain", Vec::new());
+    let origin = request.header("origin").unwrap_or("*");
+    response.headers.extend([
+        (
+            String::from("Access-Control-Allow-Origin"),
+            origin.to_string(),
+        ),
+        (
+            String::from("Access-Control-Allow-Methods"),
+            String::from("GET, HEAD, OPTIONS, POST"),
+        ),
+        (
+            String::from("Access-Control-Allow-Headers"),
+            String::from(TOKEN_NAME),
+        ),
+        (
+            String::from("Access-Control-Allow-Credentials"),
+            String::from("true"),
+        ),
+    ]);
+    response
+}
+
+fn text(value: Option<&CborValue>) -> Option<&str> {
+    match value? {
+        CborValue::Text(text) => Some(text),
+        _ => None,
+    }
+}
+
+fn texts(value: Option<&CborValue>) -> Vec<&str> {
+    match value {
+        Some(CborValue::Array(items)) => items.iter().filter_map(|i| text(Some(i))).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::claims::{ClaimSet, Renewal, RenewalType, Replay};
+    use crate::client::{RequestMethod, Worker};
+    use crate::outcome::{Failure, Redirect};
+    use crate::token::TokenType;
+    use common_access_token::current_timestamp;
+    use reqwest::header::{HeaderMap, HeaderValue, ORIGIN, USER_AGENT};
+    use std::time::Duration;
+
+    const KEY: &str = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+
+    fn request(target: &str, headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: String::from("GET"),
+            target: target.to_string(),
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn token(token_type: &TokenType) -> String {
+        let bytes = token::create_token(
+            &token::load_key(KEY).unwrap(),
+            20,
+            token_type,
+            "127.0.0.1",
+            "eyevinn",
+            &ClaimSet::default(),
+            current_timestamp(),
+        )
+        .unwrap();
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    // Starts at a whole second so tokens and checks see the same times
+    fn clock() -> Clock {
+        Clock::manual(Duration::from_secs(1_700_000_000))
+    }
+
+    #[test]
+    fn requests_from_hyper() {
+        let request = hyper::Request::builder()
+            .uri("/index.m3u8?CAT=abc")
+            .header("Host", "localhost:8080")
+            .header("Cookie", "a=1; CTA-Common-Access-Token=xyz")
+            .body(())
+            .unwrap();
+        let request = Request::from(&request);
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/index.m3u8?CAT=abc");
+        assert_eq!(request.header("HOST"), Some("localhost:8080"));
+        assert_eq!(request.cookie(TOKEN_NAME), Some("xyz"));
+        assert_eq!(
+            request.tokens(),
+            vec![
+                (Transport::Cookie, String::from("xyz")),
+                (Transport::Query, String::from("abc"))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_requests_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 3, 4).unwrap().serve(listener));
+
+        let client = reqwest::Client::new();
+        let playlist = format!("http://{}/index.m3u8", address);
+        let response = client.get(&playlist).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let token = token(&TokenType::Header);
+        let response = client
+            .head(&playlist)
+            .header(TOKEN_NAME, &token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/vnd.apple.mpegurl"
+        );
+        assert!(response.text().await.unwrap().is_empty());
+
+        // the body of a request is not used
+        let response = client
+            .post(format!("http://{}/segment_1.ts", address))
+            .header(TOKEN_NAME, &token)
+            .body("body")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "segment 1\n");
+    }
+
+    #[test]
+    fn respond_to_requests() {
+        let cdn = MockCdn::new(KEY, 3, 4).unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let now = current_timestamp();
+
+        let response = cdn.respond(&request("/index.m3u8", &[]), ip, now);
+        assert_eq!(response.status, 401);
+
+        let header_token = token(&TokenType::Header);
+        let response = cdn.respond(
+            &request("/index.m3u8", &[(TOKEN_NAME, &header_token)]),
+            ip,
+            now,
+        );
+        assert_eq!(response.status, 200);
+        assert!(String::from_utf8_lossy(&response.body).contains("segment_2.ts\n"));
+        // the token is renewed once the deadline half a ttl after iat has passed
+        assert!(!response.headers.iter().any(|(n, _)| n == TOKEN_NAME));
+        let response = cdn.respond(
+            &request("/index.m3u8", &[(TOKEN_NAME, &header_token)]),
+            ip,
+            now + 11,
+        );
+        assert!(response.headers.iter().any(|(n, _)| n == TOKEN_NAME));
+
+        let response = cdn.respond(
+            &request("/segment_3.ts", &[(TOKEN_NAME, &header_token)]),
+            ip,
+            now,
+        );
+        assert_eq!(response.status, 404);
+
+        let response = cdn.respond(
+            &request("/segment_0.ts", &[(TOKEN_NAME, &header_token)]),
+            ip,
+            now + 3600,
+        );
+        assert_eq!(response.status, 401);
+
+        // a token in the query is moved into a cookie before the deadline
+        let query_token = token(&TokenType::CookieAsQuery);
+        let response = cdn.respond(
+            &request(&format!("/index.m3u8?CAT={}", query_token), &[]),
+            ip,
+            now,
+        );
+        assert_eq!(response.status, 200);
+        let (_, cookie) = response
+            .headers
+            .iter()
+            .find(|(n, _)| n == "Set-Cookie")
+            .unwrap();
+        assert!(cookie.starts_with("CTA-Common-Access-Token="));
+        assert!(!cookie.contains("Secure"));
+
+        let wrong_key = MockCdn::new(&"00".repeat(32), 3, 4).unwrap();
+        let response = wrong_key.respond(
+            &request("/index.m3u8", &[(TOKEN_NAME, &header_token)]),
+            ip,
+            now,
+        );
+        assert_eq!(response.status, 401);
+    }
+
+    #[tokio::test]
+    async fn worker_runs_against_mock_cdn() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let clock = clock();
+        tokio::spawn(
+            MockCdn::new(KEY, 4, 4)
+                .unwrap()
+                .with_clock(clock.clone())
+                .serve(listener),
+        );
+
+        let worker = |token_type| {
+            Worker::new(
+                KEY,
+                &format!("http://{}/index.m3u8", address),
+                20,
+                token_type,
+                "eyevinn",
+                4,
+                0,
+            )
+            .unwrap()
+            .with_mode(crate::client::Mode::Vod)
+            .with_clock(clock.clone())
+            .unwrap()
+        };
+        // segments at 0, 4, 8 and 12 seconds, the deadline is at 10
+        for (token_type, renewed) in [
+            (TokenType::Header, [false, false, false, false, true]),
+            (TokenType::Cookie, [false, false, false, false, true]),
+            (TokenType::CookieAsQuery, [true, false, false, false, true]),
+        ] {
+            let report = worker(token_type).run().await.unwrap();
+            assert!(report.passed(), "{:?}", report);
+            let outcomes: Vec<bool> = report.outcomes.iter().map(|o| o.renewed).collect();
+            assert_eq!(outcomes, renewed);
+        }
+
+        // a renewal header is not a cookie, the token is not renewed in time
+        let claims = ClaimSet::default().with_renewal(Renewal {
+            kind: Some(RenewalType::Header),
+            ..Renewal::default()
+        });
+        let report = worker(TokenType::Cookie)
+            .with_claims(claims)
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        let failures: Vec<&[Failure]> = report
+            .outcomes
+            .iter()
+            .map(|o| o.failures.as_slice())
+            .collect();
+        assert_eq!(
+            failures,
+            [&[][..], &[], &[], &[], &[Failure::MissingRenewal]]
+        );
+    }
+
+    #[tokio::test]
+    async fn segments_outside_catu_path_are_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 3, 4).unwrap().serve(listener));
+
+        let run = |catu: &str| {
+            let claims = ClaimSet::default().with_catu(vec![catu.parse().unwrap()]);
+            Worker::new(
+                KEY,
+                &format!("http://{}/live/index.m3u8", address),
+                20,
+                TokenType::Header,
+                "eyevinn",
+                3,
+                0,
+            )
+            .unwrap()
+            .with_claims(claims)
+            .unwrap()
+            .with_mode(crate::client::Mode::Vod)
+            .with_speed(0.0)
+        };
+        let report = run("path:prefix:/live/").run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        // the playlist is allowed but not the segments next to it
+        let report = run("path:prefix:/live/index").run().await.unwrap();
+        assert_eq!(report.outcomes.len(), 4);
+        assert!(report.outcomes[0].passed());
+        assert!(
+            report.outcomes[1..]
+                .iter()
+                .all(|o| o.status == 403 && o.failures.contains(&Failure::Status(403)))
+        );
+    }
+
+    #[tokio::test]
+    async fn methods_are_checked_against_catm() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 2, 4).unwrap().serve(listener));
+
+        let run = |method: RequestMethod| {
+            let claims =
+                ClaimSet::default().with_catm(vec![String::from("GET"), String::from("HEAD")]);
+            Worker::new(
+                KEY,
+                &format!("http://{}/index.m3u8", address),
+                20,
+                TokenType::Cookie,
+                "eyevinn",
+                2,
+                0,
+            )
+            .unwrap()
+            .with_claims(claims)
+            .unwrap()
+            .with_method(method)
+            .with_mode(crate::client::Mode::Vod)
+            .with_speed(0.0)
+        };
+        let report = run(RequestMethod::Head).run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        // the playlist is always fetched with GET
+        let report = run(RequestMethod::Post).run().await.unwrap();
+        assert_eq!(report.outcomes[0].status, 200);
+        assert!(report.outcomes[1..].iter().all(|o| o.status == 403));
+
+        // the preflight is answered without a token even if catm does not list OPTIONS
+        let report = run(RequestMethod::Options).run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert!(
+            report.outcomes[1..]
+                .iter()
+                .all(|o| o.status == 204 && !o.renewed)
+        );
+    }
+
+    #[tokio::test]
+    async fn client_address_is_checked_against_catnip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 2, 4).unwrap().serve(listener));
+
+        let run = |catnip: &str| {
+            let claims = ClaimSet::default().with_catnip(vec![catnip.parse().unwrap()]);
+            Worker::new(
+                KEY,
+                &format!("http://{}/index.m3u8", address),
+                20,
+                TokenType::Header,
+                "eyevinn",
+                2,
+                0,
+            )
+            .unwrap()
+            .with_claims(claims)
+            .unwrap()
+            .with_local_address(Some("127.0.0.1".parse().unwrap()))
+            .unwrap()
+            .with_mode(crate::client::Mode::Vod)
+            .with_speed(0.0)
+        };
+        let report = run("127.0.0.0/8").run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        // the playlist is already rejected
+        let report = run("10.0.0.0/8").run().await.unwrap();
+        assert_eq!(report.outcomes.len(), 1);
+        assert_eq!(report.outcomes[0].failures, vec![Failure::Status(403)]);
+    }
+
+    #[test]
+    fn pretend_client_location() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let now = current_timestamp();
+        let claims = ClaimSet::default()
+            .with_catgeoiso3166(vec![String::from("SE")])
+            .with_geohash(vec![String::from("u6sc")]);
+        let bytes = token::create_token(
+            &token::load_key(KEY).unwrap(),
+            20,
+            &TokenType::Header,
+            "127.0.0.1",
+            "eyevinn",
+            &claims,
+            now,
+        )
+        .unwrap();
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        let request = request("/index.m3u8", &[(TOKEN_NAME, &token)]);
+
+        let status = |country: &str, geohash: &str| {
+            MockCdn::new(KEY, 3, 4)
+                .unwrap()
+                .with_country(Some(country.to_string()))
+                .with_geohash(Some(geohash.to_string()))
+                .respond(&request, ip, now)
+                .status
+        };
+        assert_eq!(status("SE", "u6scd"), 200);
+        assert_eq!(status("SE-AB", "u6sc"), 200);
+        assert_eq!(status("NO", "u6scd"), 403);
+        assert_eq!(status("SE", "u4pr"), 403);
+        // without a location the geo claims are not checked
+        let response = MockCdn::new(KEY, 3, 4).unwrap().respond(&request, ip, now);
+        assert_eq!(response.status, 200);
+    }
+
+    #[test]
+    fn prohibited_replay_needs_a_cti() {
+        use common_access_token::{Algorithm, KeyId, RegisteredClaims, TokenBuilder};
+        let cdn = MockCdn::new(KEY, 3, 4).unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let now = current_timestamp();
+        let respond = |replay: i64| {
+            let token = TokenBuilder::new()
+                .algorithm(Algorithm::HmacSha256)
+                .unprotected_key_id(KeyId::string("Symmetric256"))
+                .registered_claims(
+                    RegisteredClaims::new()
+                        .with_issued_at(now)
+                        .with_expiration(now + 20),
+                )
+                .custom_int(cat_keys::CATREPLAY, replay)
+                .sign(&hex::decode(KEY).unwrap())
+                .unwrap();
+            let encoded = URL_SAFE_NO_PAD.encode(token.to_bytes().unwrap());
+            cdn.respond(&request("/index.m3u8", &[(TOKEN_NAME, &encoded)]), ip, now)
+                .status
+        };
+        assert_eq!(respond(1), 401);
+        // reuse is only detected, a token without cti is not tracked
+        assert_eq!(respond(2), 200);
+        assert_eq!(respond(2), 200);
+    }
+
+    #[tokio::test]
+    async fn replayed_token_is_rejected_when_prohibited() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 2, 4).unwrap().serve(listener));
+
+        for (replay, rejected) in [(Replay::Prohibited, true), (Replay::Permitted, false)] {
+            let worker = Worker::new(
+                KEY,
+                &format!("http://{}/index.m3u8", address),
+                20,
+                TokenType::Cookie,
+                "eyevinn",
+                2,
+                0,
+            )
+            .unwrap()
+            // a token that can not be reused is renewed on every request
+            .with_claims(
+                ClaimSet::default()
+                    .with_catreplay(Some(replay))
+                    .with_renewal(Renewal {
+                        deadline: Some(0),
+                        ..Renewal::default()
+                    }),
+            )
+            .unwrap()
+            .with_mode(crate::client::Mode::Vod)
+            .with_speed(0.0);
+            let report = worker.run().await.unwrap();
+            assert!(report.passed(), "{:?}", report);
+
+            // the second worker has its own cookie jar with the first token
+            let report = worker.independent().unwrap().run().await.unwrap();
+            if rejected {
+                assert_eq!(report.outcomes.len(), 1);
+                assert_eq!(report.outcomes[0].status, 401);
+            } else {
+                assert!(report.passed(), "{:?}", report);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn request_headers_are_checked_against_cath() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 2, 4).unwrap().serve(listener));
+
+        let run = |user_agent: &'static str| {
+            let claims = ClaimSet::default().with_cath(vec![
+                "User-Agent:prefix:TestPlayer/".parse().unwrap(),
+                "Origin:exact:https://player.example.com".parse().unwrap(),
+            ]);
+            let headers = HeaderMap::from_iter([
+                (USER_AGENT, HeaderValue::from_static(user_agent)),
+                (
+                    ORIGIN,
+                    HeaderValue::from_static("https://player.example.com"),
+                ),
+            ]);
+            Worker::new(
+                KEY,
+                &format!("http://{}/index.m3u8", address),
+                20,
+                TokenType::Header,
+                "eyevinn",
+                2,
+                0,
+            )
+            .unwrap()
+            .with_claims(claims)
+            .unwrap()
+            .with_headers(headers)
+            .with_mode(crate::client::Mode::Vod)
+            .with_speed(0.0)
+        };
+        let report = run("TestPlayer/1.0").run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        let report = run("curl/8.0").run().await.unwrap();
+        assert_eq!(report.outcomes.len(), 1);
+        assert_eq!(report.outcomes[0].status, 403);
+    }
+
+    #[test]
+    fn catv_and_catif_are_checked() {
+        let cdn = MockCdn::new(KEY, 3, 4).unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let now = current_timestamp();
+        let respond = |claims: ClaimSet, target: &str| {
+            let token = token::create_token(
+                &token::load_key(KEY).unwrap(),
+                20,
+                &TokenType::Header,
+                "",
+                "eyevinn",
+                &claims,
+                now,
+            )
+            .unwrap();
+            let encoded = URL_SAFE_NO_PAD.encode(token);
+            cdn.respond(&request(target, &[(TOKEN_NAME, &encoded)]), ip, now)
+        };
+
+        let version = |catv| ClaimSet::default().with_catv(Some(catv));
+        assert_eq!(respond(version(1), "/index.m3u8").status, 200);
+        let response = respond(version(2), "/index.m3u8");
+        assert_eq!(response.status, 403);
+        assert!(String::from_utf8_lossy(&response.body).contains("unsupported version 2"));
+
+        let catif = ClaimSet::default()
+            .with_catu(vec!["path:prefix:/live/".parse().unwrap()])
+            .with_catif(
+                Some(302),
+                vec![
+                    "Location: https://auth.example.com/?d={catifdata}"
+                        .parse()
+                        .unwrap(),
+                ],
+            )
+            .with_catifdata(Some(String::from("abc")));
+        assert_eq!(respond(catif.clone(), "/live/index.m3u8").status, 200);
+        let response = respond(catif, "/vod/index.m3u8");
+        assert_eq!(response.status, 302);
+        assert!(response.headers.contains(&(
+            String::from("Location"),
+            String::from("https://auth.example.com/?d=abc")
+        )));
+        let response = response.into_hyper();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers()["location"],
+            "https://auth.example.com/?d=abc"
+        );
+    }
+
+    #[tokio::test]
+    async fn worker_records_catif_redirects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(MockCdn::new(KEY, 2, 4).unwrap().serve(listener));
+
+        let run = |path: &str, catu: &str, location: &str| {
+            let claims = ClaimSet::default()
+                .with_catu(vec![catu.parse().unwrap()])
+                .with_catif(Some(302), vec![location.parse().unwrap()]);
+            Worker::new(
+                KEY,
+                &format!("http://{}{}", address, path),
+                20,
+                TokenType::Header,
+                "eyevinn",
+                2,
+                0,
+            )
+            .unwrap()
+            .with_claims(claims)
+            .unwrap()
+            .with_mode(crate::client::Mode::Vod)
+            .with_speed(0.0)
+        };
+
+        // segments are resolved against the playlist the worker was redirected to
+        let report = run(
+            "/vod/index.m3u8",
+            "path:prefix:/live/",
+            "Location: /live/index.m3u8",
+        )
+        .run()
+        .await
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.outcomes[0].redirects.len(), 1);
+        assert!(report.outcomes[1].url.ends_with("/live/segment_0.ts"));
+
+        let report = run(
+            "/index.m3u8",
+            "filename:regex:^(index\\.m3u8|segment_0\\.ts)$",
+            "Location: segment_0.ts",
+        )
+        .run()
+        .await
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert!(report.outcomes[1].redirects.is_empty());
+        assert_eq!(
+            report.outcomes[2].redirects,
+            vec![Redirect {
+                status: 302,
+                url: format!("http://{}/segment_1.ts", address),
+                location: String::from("segment_0.ts"),
+            }]
+        );
+    }
+
+    #[test]
+    fn renewal_by_redirect_and_header_params() {
+        let cdn = MockCdn::new(KEY, 3, 4).unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let create = |renewal: Renewal| {
+            let claims = ClaimSet::default().with_renewal(renewal);
+            let token = token::create_token(
+                &token::load_key(KEY).unwrap(),
+                20,
+                &TokenType::Header,
+                "",
+                "eyevinn",
+                &claims,
+                current_timestamp(),
+            )
+            .unwrap();
+            URL_SAFE_NO_PAD.encode(token)
+        };
+
+        let redirect = create(Renewal {
+            kind: Some(RenewalType::Redirect),
+            deadline: Some(5),
+            code: Some(307),
+            ..Renewal::default()
+        });
+        let now = inspect::decode(&redirect)
+            .unwrap()
+            .claims
+            .registered
+            .iat
+            .unwrap();
+        let target = "/segment_0.ts?a=1";
+        let response = cdn.respond(&request(target, &[(TOKEN_NAME, &redirect)]), ip, now);
+        assert_eq!(response.status, 200);
+        let response = cdn.respond(&request(target, &[(TOKEN_NAME, &redirect)]), ip, now + 6);
+        assert_eq!(response.status, 307);
+        let (_, location) = response
+            .headers
+            .iter()
+            .find(|(n, _)| n == "Location")
+            .unwrap();
+        let location = Url::parse(location).unwrap();
+        assert_eq!(location.path(), "/segment_0.ts");
+        let renewed: Vec<_> = location
+            .query_pairs()
+            .map(|(n, _)| n.into_owned())
+            .collect();
+        assert_eq!(renewed, vec!["a", TOKEN_QUERY]);
+
+        // the renewed token in the query is newer than the one in the header
+        let target = format!("{}?{}", location.path(), location.query().unwrap());
+        let response = cdn.respond(&request(&target, &[(TOKEN_NAME, &redirect)]), ip, now + 6);
+        assert_eq!(response.status, 200);
+
+        let header = create(Renewal {
+            name: Some(String::from("X-Token")),
+            params: Some(vec![String::from("max-age=20")]),
+            exp_add: Some(5),
+            ..Renewal::default()
+        });
+        let response = cdn.respond(
+            &request("/index.m3u8", &[(TOKEN_NAME, &header)]),
+            ip,
+            now + 11,
+        );
+        let (_, renewed) = response
+            .headers
+            .iter()
+            .find(|(n, _)| n == "X-Token")
+            .unwrap();
+        let (renewed, params) = renewed.split_once("; ").unwrap();
+        assert_eq!(params, "max-age=20");
+        // a renewed token is sent back with the name in its catr claim, it
+        // is used and has expired while the first token is still valid
+        let response = cdn.respond(
+            &request(
+                "/index.m3u8",
+                &[(TOKEN_NAME, &header), ("X-Token", renewed)],
+            ),
+            ip,
+            now + 17,
+        );
+        assert_eq!(response.status, 401);
+        let response = cdn.respond(
+            &request("/index.m3u8", &[(TOKEN_NAME, &header)]),
+            ip,
+            now + 17,
+        );
+        assert_eq!(response.status, 200);
+    }
+
+    #[tokio::test]
+    async fn worker_follows_redirect_renewals() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let clock = clock();
+        tokio::spawn(
+            MockCdn::new(KEY, 2, 4)
+                .unwrap()
+                .with_clock(clock.clone())
+                .serve(listener),
+        );
+
+        let claims = ClaimSet::default().with_renewal(Renewal {
+            kind: Some(RenewalType::Redirect),
+            deadline: Some(1),
+            ..Renewal::default()
+        });
+        let report = Worker::new(
+            KEY,
+            &format!("http://{}/index.m3u8", address),
+            20,
+            TokenType::Header,
+            "eyevinn",
+            4,
+            700,
+        )
+        .unwrap()
+        .with_claims(claims)
+        .unwrap()
+        .with_clock(clock)
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+        let renewal = report
+            .outcomes
+            .iter()
+            .find(|o| o.renewed)
+            .expect("no redirect renewal");
+        assert_eq!(renewal.redirects.len(), 1);
+        assert_eq!(renewal.redirects[0].status, 302);
+    }
+
+    #[tokio::test]
+    async fn query_token_is_renewed_by_redirect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let clock = clock();
+        tokio::spawn(
+            MockCdn::new(KEY, 2, 4)
+                .unwrap()
+                .with_clock(clock.clone())
+                .serve(listener),
+        );
+
+        let worker = |claims: ClaimSet| {
+            Worker::new(
+                KEY,
+                &format!("http://{}/index.m3u8", address),
+                20,
+                TokenType::Query,
+                "eyevinn",
+                6,
+                700,
+            )
+            .unwrap()
+            .with_claims(claims)
+            .unwrap()
+            .with_clock(clock.clone())
+            .unwrap()
+        };
+        let renew_after_a_second = ClaimSet::default().with_renewal(Renewal {
+            deadline: Some(1),
+            ..Renewal::default()
+        });
+        let report = worker(renew_after_a_second).run().await.unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.renewed_in_time(), Some(true));
+        assert!(
+            report
+                .outcomes
+                .iter()
+                .any(|o| o.renewed && o.redirects.len() == 1)
+        );
+
+        // the token expires before the deadline so it is never renewed, it
+        // is still valid for the playlist request in the second it was made
+        let expires_first = ClaimSet {
+            exp: Some(2),
+            ..ClaimSet::default()
+        }
+        .with_renewal(Renewal {
+            deadline: Some(5),
+            ..Renewal::default()
+        });
+        let report = worker(expires_first).run().await.unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.renewed_in_time(), Some(false));
+        assert!(report.failed().any(|o| o.status == 401));
+    }
+}
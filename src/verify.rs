@@ -0,0 +1,909 @@
+use crate::cose;
+use crate::error::CatTesterError;
+use crate::inspect::describe_time;
+use crate::token::Key;
+use common_access_token::{
+    CborValue, Token, cat_keys, match_types, renewal_params, uri_components,
+};
+use regex::Regex;
+use reqwest::Url;
+use sha2::{Digest, Sha256, Sha512_256};
+use std::fmt;
+use std::net::IpAddr;
+
+/// Seconds a renewed exp may differ from the time it was received plus
+/// exp-add, covers clock differences and the time the request took
+const RENEWAL_EXP_TOLERANCE: u64 = 5;
+/// The CAT version this tool implements, the value of a catv claim
+pub const CAT_VERSION: i64 = 1;
+/// COSE header parameters a token may mark as critical, alg and kid
+const UNDERSTOOD_HEADERS: [i32; 2] = [cose::ALG, cose::KID];
+
+/// The request a token is checked against, claims that need a value that
+/// is not given are skipped
+#[derive(Clone, Debug, Default)]
+pub struct RequestInfo {
+    pub url: Option<Url>,
+    pub method: Option<String>,
+    pub client_ip: Option<IpAddr>,
+    /// ISO 3166 code of where the client is, like SE or US-CA
+    pub country: Option<String>,
+    /// Geohash of where the client is
+    pub geohash: Option<String>,
+    /// Headers of the request as name and value
+    pub headers: Option<Vec<(String, String)>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skipped,
+}
+
+/// Result of checking one claim
+#[derive(Clone, Debug)]
+pub struct ClaimCheck {
+    pub claim: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl ClaimCheck {
+    fn new(claim: &'static str, result: Result<String, String>) -> Self {
+        let (status, detail) = match result {
+            Ok(detail) => (CheckStatus::Pass, detail),
+            Err(detail) => (CheckStatus::Fail, detail),
+        };
+        Self {
+            claim,
+            status,
+            detail,
+        }
+    }
+
+    fn skipped(claim: &'static str, detail: &str) -> Self {
+        Self {
+            claim,
+            status: CheckStatus::Skipped,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ClaimCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skipped => "skipped",
+        };
+        write!(f, "{}: {}, {}", self.claim, status, self.detail)
+    }
+}
+
+/// Whether the MAC is valid and how each claim checks out
+#[derive(Clone, Debug)]
+pub struct Verification {
+    pub mac: Result<(), String>,
+    pub checks: Vec<ClaimCheck>,
+}
+
+impl Verification {
+    pub fn valid(&self) -> bool {
+        self.mac.is_ok() && self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &ClaimCheck> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Fail)
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mac {
+            Ok(()) => writeln!(f, "mac: valid")?,
+            Err(e) => writeln!(f, "mac: INVALID, {}", e)?,
+        }
+        for check in &self.checks {
+            writeln!(f, "{}", check)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the MAC of the encoded token with `key` and the time, url, method
+/// and ip claims at `now`
+pub fn verify(
+    token: &[u8],
+    key: &Key,
+    request: &RequestInfo,
+    now: u64,
+) -> Result<Verification, CatTesterError> {
+    let mac = cose::verify(token, key).map_err(|e| e.to_string());
+    Ok(Verification {
+        mac,
+        checks: check_claims(&cose::open(token)?, request, now),
+    })
+}
+
+/// Check the claims of a token without the key
+pub fn check_claims(token: &Token, request: &RequestInfo, now: u64) -> Vec<ClaimCheck> {
+    let registered = &token.claims.registered;
+    let mut checks = Vec::new();
+    if let Some(crit) = token.header.protected.get(&cose::CRIT) {
+        checks.push(ClaimCheck::new("crit", check_crit(crit, token)));
+    }
+    if let Some(exp) = registered.exp {
+        let result = if now < exp {
+            Ok(format!("expires {}", describe_time(exp, now)))
+        } else {
+            Err(format!("expired {}", describe_time(exp, now)))
+        };
+        checks.push(ClaimCheck::new("exp", result));
+    }
+    if let Some(nbf) = registered.nbf {
+        let result = if now >= nbf {
+            Ok(format!("valid from {}", describe_time(nbf, now)))
+        } else {
+            Err(format!(
+                "not yet valid, valid from {}",
+                describe_time(nbf, now)
+            ))
+        };
+        checks.push(ClaimCheck::new("nbf", result));
+    }
+    for (key, value) in &token.claims.custom {
+        let check = match *key {
+            cat_keys::CATU => match &request.url {
+                Some(url) => ClaimCheck::new("catu", check_catu(value, url)),
+                None => ClaimCheck::skipped("catu", "no url given"),
+            },
+            cat_keys::CATM => match &request.method {
+                Some(method) => ClaimCheck::new("catm", check_catm(value, method)),
+                None => ClaimCheck::skipped("catm", "no method given"),
+            },
+            cat_keys::CATNIP => match request.client_ip {
+                Some(ip) => ClaimCheck::new("catnip", check_catnip(value, ip)),
+                None => ClaimCheck::skipped("catnip", "no client ip given"),
+            },
+            cat_keys::CATH => match &request.headers {
+                Some(headers) => ClaimCheck::new("cath", check_cath(value, headers)),
+                None => ClaimCheck::skipped("cath", "no headers given"),
+            },
+            cat_keys::CATGEOISO3166 => match &request.country {
+                Some(country) => {
+                    ClaimCheck::new("catgeoiso3166", check_catgeoiso3166(value, country))
+                }
+                None => ClaimCheck::skipped("catgeoiso3166", "no country given"),
+            },
+            cat_keys::GEOHASH => match &request.geohash {
+                Some(geohash) => ClaimCheck::new("geohash", check_geohash(value, geohash)),
+                None => ClaimCheck::skipped("geohash", "no geohash given"),
+            },
+            cat_keys::CATV => ClaimCheck::new("catv", check_catv(value)),
+            _ => continue,
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+/// Check an encoded token the CDN handed back, received at `now`, against
+/// the token it renews. Returns the renewed token and what is wrong with it
+pub fn check_renewal(
+    previous: &Token,
+    renewed: &[u8],
+    key: &Key,
+    now: u64,
+) -> Result<(Token, Vec<String>), CatTesterError> {
+    let mut violations = Vec::new();
+    if let Err(e) = cose::verify(renewed, key) {
+        violations.push(format!("mac is not valid: {}", e));
+    }
+    let renewed = cose::open(renewed)?;
+    let before = &previous.claims.registered;
+    let after = &renewed.claims.registered;
+    if before.iss != after.iss {
+        violations.push(format!(
+            "iss changed from {:?} to {:?}",
+            before.iss, after.iss
+        ));
+    }
+    if before.sub != after.sub {
+        violations.push(format!(
+            "sub changed from {:?} to {:?}",
+            before.sub, after.sub
+        ));
+    }
+    if !renewed.claims.custom.contains_key(&cat_keys::CATR) {
+        violations.push(String::from("catr is missing"));
+    }
+    match (exp_add(previous), after.exp) {
+        (Some(exp_add), Some(exp)) => {
+            let expected = now + exp_add;
+            if exp.abs_diff(expected) > RENEWAL_EXP_TOLERANCE {
+                violations.push(format!(
+                    "exp {} is not the renewal time plus exp-add {}, expected {}",
+                    exp, exp_add, expected
+                ));
+            }
+        }
+        (_, None) => violations.push(String::from("exp is missing")),
+        (None, Some(_)) => {}
+    }
+    Ok((renewed, violations))
+}
+
+fn exp_add(token: &Token) -> Option<u64> {
+    match token.claims.custom.get(&cat_keys::CATR) {
+        Some(CborValue::Map(params)) => match params.get(&renewal_params::EXPADD) {
+            Some(CborValue::Integer(exp_add)) => u64::try_from(*exp_add).ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Every uri component in the claim must match the url
+pub fn check_catu(value: &CborValue, url: &Url) -> Result<String, String> {
+    let CborValue::Map(components) = value else {
+        return Err(String::from("claim is not a map"));
+    };
+    for (component, matches) in components {
+        let name = component_name(*component);
+        let part = uri_component(url, *component)
+            .ok_or_else(|| format!("unknown uri component {}", component))?;
+        let CborValue::Map(matches) = matches else {
+            return Err(format!("{} match is not a map", name));
+        };
+        for (kind, expected) in matches {
+            if !match_component(&part, *kind, expected)? {
+                return Err(format!(
+                    "{} {:?} does not match {}",
+                    name,
+                    part,
+                    describe_match(*kind, expected)
+                ));
+            }
+        }
+    }
+    Ok(format!("{} matches", url))
+}
+
+/// The value of a uri component as CTA-5007 defines them
+pub fn uri_component(url: &Url, component: i32) -> Option<String> {
+    let path = url.path();
+    let (parent, filename) = path.rsplit_once('/').unwrap_or(("", path));
+    let (stem, extension) = match filename.rfind('.') {
+        Some(dot) if dot > 0 => (&filename[..dot], &filename[dot..]),
+        _ => (filename, ""),
+    };
+    let value = match component {
+        uri_components::SCHEME => url.scheme().to_string(),
+        uri_components::HOST => url.host_str().unwrap_or_default().to_string(),
+        uri_components::PORT => url
+            .port_or_known_default()
+            .map(|p| p.to_string())
+            .unwrap_or_default(),
+        uri_components::PATH => path.to_string(),
+        uri_components::QUERY => url.query().unwrap_or_default().to_string(),
+        uri_components::PARENT_PATH => parent.to_string(),
+        uri_components::FILENAME => filename.to_string(),
+        uri_components::STEM => stem.to_string(),
+        uri_components::EXTENSION => extension.to_string(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn match_component(part: &str, kind: i32, expected: &CborValue) -> Result<bool, String> {
+    let matched = match (kind, expected) {
+        (match_types::EXACT, CborValue::Text(text)) => part == text,
+        (match_types::PREFIX, CborValue::Text(text)) => part.starts_with(text.as_str()),
+        (match_types::SUFFIX, CborValue::Text(text)) => part.ends_with(text.as_str()),
+        (match_types::CONTAINS, CborValue::Text(text)) => part.contains(text.as_str()),
+        (match_types::REGEX, CborValue::Array(items)) => match items.first() {
+            Some(CborValue::Text(pattern)) => Regex::new(pattern)
+                .map_err(|e| format!("invalid regex {}: {}", pattern, e))?
+                .is_match(part),
+            _ => return Err(String::from("regex match has no pattern")),
+        },
+        (match_types::REGEX, CborValue::Text(pattern)) => Regex::new(pattern)
+            .map_err(|e| format!("invalid regex {}: {}", pattern, e))?
+            .is_match(part),
+        (match_types::SHA256, CborValue::Bytes(hash)) => {
+            Sha256::digest(part.as_bytes()).as_slice() == hash.as_slice()
+        }
+        (match_types::SHA512_256, CborValue::Bytes(hash)) => {
+            Sha512_256::digest(part.as_bytes()).as_slice() == hash.as_slice()
+        }
+        _ => return Err(format!("unsupported match type {}", kind)),
+    };
+    Ok(matched)
+}
+
+fn describe_match(kind: i32, expected: &CborValue) -> String {
+    let name = match kind {
+        match_types::EXACT => "exact",
+        match_types::PREFIX => "prefix",
+        match_types::SUFFIX => "suffix",
+        match_types::CONTAINS => "contains",
+        match_types::REGEX => "regex",
+        match_types::SHA256 => "sha-256",
+        match_types::SHA512_256 => "sha-512/256",
+        _ => "unknown",
+    };
+    match expected {
+        CborValue::Text(text) => format!("{} {:?}", name, text),
+        CborValue::Array(items) => match items.first() {
+            Some(CborValue::Text(pattern)) => format!("{} {:?}", name, pattern),
+            _ => name.to_string(),
+        },
+        CborValue::Bytes(bytes) => format!("{} {}", name, hex::encode(bytes)),
+        _ => name.to_string(),
+    }
+}
+
+fn component_name(component: i32) -> &'static str {
+    match component {
+        uri_components::SCHEME => "scheme",
+        uri_components::HOST => "host",
+        uri_components::PORT => "port",
+        uri_components::PATH => "path",
+        uri_components::QUERY => "query",
+        uri_components::PARENT_PATH => "parent-path",
+        uri_components::FILENAME => "filename",
+        uri_components::STEM => "stem",
+        uri_components::EXTENSION => "extension",
+        _ => "unknown",
+    }
+}
+
+/// The method must be one of the methods in the claim, a text with comma
+/// separated methods or an array of texts
+pub fn check_catm(value: &CborValue, method: &str) -> Result<String, String> {
+    let methods: Vec<String> = match value {
+        CborValue::Text(text) => text.split(',').map(|m| m.trim().to_string()).collect(),
+        CborValue::Array(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                CborValue::Text(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => return Err(String::from("claim is not a text or an array")),
+    };
+    if methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+        Ok(format!("{} is allowed", method))
+    } else {
+        Err(format!("{} is not one of {}", method, methods.join(", ")))
+    }
+}
+
+/// The ip must be in one of the networks in the claim. The claim is the
+/// encoded array of networks, see cose::open. A network is an ASN, or an
+/// address or a [prefix length, address bytes] with the RFC 9164 tag of the
+/// address family. An ASN can not be looked up here and never matches
+pub fn check_catnip(value: &CborValue, ip: IpAddr) -> Result<String, String> {
+    let CborValue::Bytes(raw) = value else {
+        return Err(String::from("catnip is not an array of networks"));
+    };
+    let mut described = Vec::new();
+    for network in cose::items(raw)? {
+        if let Ok(CborValue::Integer(asn)) = cose::value(network) {
+            described.push(format!("AS{} (not checked)", asn));
+            continue;
+        }
+        let (address, prefix) = parse_network(network)?;
+        if in_network(ip, address, prefix) {
+            return Ok(format!("{} is in {}/{}", ip, address, prefix));
+        }
+        described.push(format!("{}/{}", address, prefix));
+    }
+    Err(format!("{} is not in {}", ip, described.join(", ")))
+}
+
+// The tag gives the address family
+fn parse_network(network: &[u8]) -> Result<(IpAddr, u8), String> {
+    let (ipv6, network) = match cose::tagged(network)? {
+        Some((cose::IPV4_TAG, network)) => (false, network),
+        Some((cose::IPV6_TAG, network)) => (true, network),
+        Some((tag, _)) => return Err(format!("network has tag {}, not 52 or 54", tag)),
+        None => return Err(String::from("network has no address family tag")),
+    };
+    match cose::value(network)? {
+        CborValue::Bytes(bytes) => {
+            let address = address_from_bytes(&bytes, ipv6)?;
+            Ok((address, if ipv6 { 128 } else { 32 }))
+        }
+        CborValue::Array(items) => match items.as_slice() {
+            [CborValue::Integer(prefix), CborValue::Bytes(bytes)] => {
+                let address = address_from_bytes(bytes, ipv6)?;
+                Ok((address, prefix_length(address, *prefix)?))
+            }
+            _ => Err(String::from("network is not [prefix length, address]")),
+        },
+        _ => Err(String::from("network is not an address")),
+    }
+}
+
+// A prefix length must fit the address, 0 to 32 for IPv4 and 0 to 128 for IPv6
+fn prefix_length(address: IpAddr, prefix: i64) -> Result<u8, String> {
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    u8::try_from(prefix)
+        .ok()
+        .filter(|prefix| *prefix <= max)
+        .ok_or_else(|| format!("invalid prefix length {} for {}", prefix, address))
+}
+
+// RFC 9164 drops trailing zero bytes of a prefix, pad them back
+fn address_from_bytes(bytes: &[u8], ipv6: bool) -> Result<IpAddr, String> {
+    let invalid = || format!("invalid address {}", hex::encode(bytes));
+    if ipv6 {
+        let mut octets = [0u8; 16];
+        octets
+            .get_mut(..bytes.len())
+            .ok_or_else(invalid)?
+            .copy_from_slice(bytes);
+        Ok(IpAddr::from(octets))
+    } else {
+        let mut octets = [0u8; 4];
+        octets
+            .get_mut(..bytes.len())
+            .ok_or_else(invalid)?
+            .copy_from_slice(bytes);
+        Ok(IpAddr::from(octets))
+    }
+}
+
+/// Every header in the claim must be in the request and match. The claim is
+/// the encoded map from header name to match, see cose::open
+pub fn check_cath(value: &CborValue, headers: &[(String, String)]) -> Result<String, String> {
+    let CborValue::Bytes(raw) = value else {
+        return Err(String::from("claim is not a map with header names"));
+    };
+    let mut names = Vec::new();
+    for (name, matches) in cose::text_entries(raw)? {
+        let CborValue::Map(matches) = cose::value(matches)? else {
+            return Err(format!("{} match is not a map", name));
+        };
+        let actual = headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| format!("{} header is missing", name))?;
+        for (kind, expected) in &matches {
+            if !match_component(actual, *kind, expected)? {
+                return Err(format!(
+                    "{} {:?} does not match {}",
+                    name,
+                    actual,
+                    describe_match(*kind, expected)
+                ));
+            }
+        }
+        names.push(name);
+    }
+    Ok(format!("{} match", names.join(", ")))
+}
+
+/// The token must be of the CAT version this tool implements
+pub fn check_catv(value: &CborValue) -> Result<String, String> {
+    match value {
+        CborValue::Integer(version) if *version == CAT_VERSION => {
+            Ok(format!("version {}", version))
+        }
+        CborValue::Integer(version) => Err(format!("unsupported version {}", version)),
+        _ => Err(String::from("claim is not an integer")),
+    }
+}
+
+/// Every critical header parameter must be understood and be in the
+/// protected header (RFC 9052 section 3.1)
+pub fn check_crit(value: &CborValue, token: &Token) -> Result<String, String> {
+    let CborValue::Array(labels) = value else {
+        return Err(String::from("crit is not an array"));
+    };
+    if labels.is_empty() {
+        return Err(String::from("crit is empty"));
+    }
+    let mut names = Vec::new();
+    for label in labels {
+        let label = match label {
+            CborValue::Integer(label) => i32::try_from(*label)
+                .ok()
+                .filter(|label| UNDERSTOOD_HEADERS.contains(label))
+                .ok_or_else(|| format!("unknown critical parameter {}", label))?,
+            CborValue::Text(label) => {
+                return Err(format!("unknown critical parameter {:?}", label));
+            }
+            _ => return Err(String::from("crit label is not an integer or a text")),
+        };
+        if !token.header.protected.contains_key(&label) {
+            return Err(format!(
+                "critical parameter {} is not in the protected header",
+                label
+            ));
+        }
+        names.push(label.to_string());
+    }
+    Ok(format!("{} understood", names.join(", ")))
+}
+
+/// The country must be one of the codes in the claim. A country code also
+/// allows its subdivisions, US allows US-CA but US-CA only allows US-CA
+pub fn check_catgeoiso3166(value: &CborValue, country: &str) -> Result<String, String> {
+    let codes = texts(value).ok_or_else(|| String::from("claim is not a text or an array"))?;
+    let allowed = codes.iter().find(|code| {
+        country.eq_ignore_ascii_case(code)
+            || country
+                .split_once('-')
+                .is_some_and(|(c, _)| c.eq_ignore_ascii_case(code))
+    });
+    match allowed {
+        Some(code) => Ok(format!("{} is in {}", country, code)),
+        None => Err(format!("{} is not in {}", country, codes.join(", "))),
+    }
+}
+
+/// The geohash must be inside one of the geohash cells in the claim, a
+/// location is inside a cell if its geohash starts with the cell
+pub fn check_geohash(value: &CborValue, geohash: &str) -> Result<String, String> {
+    let cells = texts(value).ok_or_else(|| String::from("claim is not a text or an array"))?;
+    let geohash = geohash.to_lowercase();
+    match cells
+        .iter()
+        .find(|cell| geohash.starts_with(&cell.to_lowercase()))
+    {
+        Some(cell) => Ok(format!("{} is in {}", geohash, cell)),
+        None => Err(format!("{} is not in {}", geohash, cells.join(", "))),
+    }
+}
+
+// A text or the texts in an array
+fn texts(value: &CborValue) -> Option<Vec<String>> {
+    match value {
+        CborValue::Text(text) => Some(vec![text.clone()]),
+        CborValue::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    CborValue::Text(text) => Some(text.clone()),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix.min(32)))
+                .unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix.min(128)))
+                .unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common_access_token::{Algorithm, KeyId, RegisteredClaims, TokenBuilder, catu};
+    use std::collections::BTreeMap;
+
+    const KEY: &str = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+
+    fn catu_claim(components: Vec<(i32, BTreeMap<i32, CborValue>)>) -> CborValue {
+        CborValue::Map(catu::create(components.into_iter().collect()))
+    }
+
+    #[test]
+    fn uri_components_of_url() {
+        let url = Url::parse("https://cdn.example.com/live/stream/index.m3u8?a=1").unwrap();
+        let component = |c| uri_component(&url, c).unwrap();
+        assert_eq!(component(uri_components::SCHEME), "https");
+        assert_eq!(component(uri_components::HOST), "cdn.example.com");
+        assert_eq!(component(uri_components::PORT), "443");
+        assert_eq!(component(uri_components::PATH), "/live/stream/index.m3u8");
+        assert_eq!(component(uri_components::QUERY), "a=1");
+        assert_eq!(component(uri_components::PARENT_PATH), "/live/stream");
+        assert_eq!(component(uri_components::FILENAME), "index.m3u8");
+        assert_eq!(component(uri_components::STEM), "index");
+        assert_eq!(component(uri_components::EXTENSION), ".m3u8");
+    }
+
+    #[test]
+    fn catu_matches() {
+        let url = Url::parse("https://cdn.example.com/live/index.m3u8").unwrap();
+        let claim = catu_claim(vec![
+            (uri_components::SCHEME, catu::exact_match("https")),
+            (uri_components::HOST, catu::suffix_match(".example.com")),
+            (uri_components::PATH, catu::prefix_match("/live/")),
+            (
+                uri_components::FILENAME,
+                catu::regex_match(r"^index\.", vec![]),
+            ),
+            (
+                uri_components::EXTENSION,
+                catu::sha256_match(&Sha256::digest(b".m3u8")),
+            ),
+        ]);
+        assert!(check_catu(&claim, &url).is_ok());
+
+        let claim = catu_claim(vec![(uri_components::PATH, catu::contains_match("/vod/"))]);
+        assert_eq!(
+            check_catu(&claim, &url),
+            Err(String::from(
+                "path \"/live/index.m3u8\" does not match contains \"/vod/\""
+            ))
+        );
+    }
+
+    #[test]
+    fn cath_matches() {
+        let claim = CborValue::Bytes(cose::encode_text_map(&[(
+            "User-Agent",
+            cose::encode(&CborValue::Map(catu::prefix_match("TestPlayer/"))),
+        )]));
+        let headers = |ua: &str| vec![(String::from("user-agent"), ua.to_string())];
+        assert!(check_cath(&claim, &headers("TestPlayer/1.0")).is_ok());
+        assert_eq!(
+            check_cath(&claim, &headers("curl/8.0")),
+            Err(String::from(
+                "User-Agent \"curl/8.0\" does not match prefix \"TestPlayer/\""
+            ))
+        );
+        assert_eq!(
+            check_cath(&claim, &[]),
+            Err(String::from("User-Agent header is missing"))
+        );
+    }
+
+    #[test]
+    fn catv_and_crit_are_checked() {
+        assert!(check_catv(&CborValue::Integer(CAT_VERSION)).is_ok());
+        assert_eq!(
+            check_catv(&CborValue::Integer(2)),
+            Err(String::from("unsupported version 2"))
+        );
+
+        let key = hex::decode(KEY).unwrap();
+        let mut token = TokenBuilder::new()
+            .algorithm(Algorithm::HmacSha256)
+            .protected_key_id(KeyId::string("kid"))
+            .registered_claims(RegisteredClaims::new())
+            .sign(&key)
+            .unwrap();
+        let crit = |labels: Vec<CborValue>| CborValue::Array(labels);
+        assert!(
+            check_crit(
+                &crit(vec![CborValue::Integer(1), CborValue::Integer(4)]),
+                &token
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            check_crit(&crit(vec![CborValue::Integer(99)]), &token),
+            Err(String::from("unknown critical parameter 99"))
+        );
+        assert!(check_crit(&crit(vec![]), &token).is_err());
+
+        token
+            .header
+            .protected
+            .insert(cose::CRIT, crit(vec![CborValue::Integer(99)]));
+        let checks = check_claims(&token, &RequestInfo::default(), 0);
+        assert_eq!(checks[0].claim, "crit");
+        assert_eq!(checks[0].status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn geo_matches() {
+        let countries = CborValue::Array(vec![
+            CborValue::Text(String::from("SE")),
+            CborValue::Text(String::from("US-CA")),
+        ]);
+        assert!(check_catgeoiso3166(&countries, "se").is_ok());
+        assert!(check_catgeoiso3166(&countries, "SE-AB").is_ok());
+        assert!(check_catgeoiso3166(&countries, "US-CA").is_ok());
+        assert_eq!(
+            check_catgeoiso3166(&countries, "US-NY"),
+            Err(String::from("US-NY is not in SE, US-CA"))
+        );
+
+        let cells = CborValue::Array(vec![CborValue::Text(String::from("u6sc"))]);
+        assert!(check_geohash(&cells, "u6scd").is_ok());
+        assert!(check_geohash(&cells, "u6s").is_err());
+        assert!(check_geohash(&CborValue::Integer(1), "u6scd").is_err());
+    }
+
+    #[test]
+    fn catm_matches() {
+        let claim = CborValue::Array(vec![
+            CborValue::Text(String::from("GET")),
+            CborValue::Text(String::from("HEAD")),
+        ]);
+        assert!(check_catm(&claim, "GET").is_ok());
+        assert_eq!(
+            check_catm(&claim, "POST"),
+            Err(String::from("POST is not one of GET, HEAD"))
+        );
+        assert!(check_catm(&CborValue::Text(String::from("GET, POST")), "POST").is_ok());
+    }
+
+    #[test]
+    fn catnip_matches() {
+        let prefix = |tag, prefix, bytes: &[u8]| {
+            let prefix = CborValue::Array(vec![
+                CborValue::Integer(prefix),
+                CborValue::Bytes(bytes.to_vec()),
+            ]);
+            cose::encode_tagged(tag, &cose::encode(&prefix))
+        };
+        let claim = |networks: &[Vec<u8>]| CborValue::Bytes(cose::encode_array(networks));
+        let address = cose::encode_tagged(
+            cose::IPV4_TAG,
+            &cose::encode(&CborValue::Bytes(vec![192, 168, 1, 1])),
+        );
+        let networks = claim(&[
+            prefix(cose::IPV4_TAG, 8, &[10]),
+            address,
+            // a tagged IPv6 /32, 54([32, h'20010db8'])
+            prefix(cose::IPV6_TAG, 32, &[0x20, 0x01, 0x0d, 0xb8]),
+        ]);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(check_catnip(&networks, ip("10.1.2.3")).is_ok());
+        assert!(check_catnip(&networks, ip("192.168.1.1")).is_ok());
+        assert_eq!(
+            check_catnip(&networks, ip("2001:db8::1")),
+            Ok(String::from("2001:db8::1 is in 2001:db8::/32"))
+        );
+        assert_eq!(
+            check_catnip(&networks, ip("192.168.1.2")),
+            Err(String::from(
+                "192.168.1.2 is not in 10.0.0.0/8, 192.168.1.1/32, 2001:db8::/32"
+            ))
+        );
+        // the tag and not the length of the bytes gives the address family
+        let short_ipv6 = claim(&[prefix(cose::IPV6_TAG, 8, &[10])]);
+        assert!(check_catnip(&short_ipv6, ip("10.1.2.3")).is_err());
+        assert!(check_catnip(&short_ipv6, ip("a00::1")).is_ok());
+        let untagged = claim(&[cose::encode(&CborValue::Bytes(vec![10, 1, 2, 3]))]);
+        assert!(check_catnip(&untagged, ip("10.1.2.3")).is_err());
+        let asn = claim(&[cose::encode(&CborValue::Integer(64500))]);
+        assert_eq!(
+            check_catnip(&asn, ip("10.1.2.3")),
+            Err(String::from("10.1.2.3 is not in AS64500 (not checked)"))
+        );
+
+        assert_eq!(
+            check_catnip(&claim(&[prefix(cose::IPV4_TAG, -1, &[10])]), ip("10.1.2.3")),
+            Err(String::from("invalid prefix length -1 for 10.0.0.0"))
+        );
+        assert!(
+            check_catnip(&claim(&[prefix(cose::IPV4_TAG, 40, &[10])]), ip("10.1.2.3")).is_err()
+        );
+        assert!(
+            check_catnip(
+                &claim(&[prefix(cose::IPV4_TAG, 8, &[10; 5])]),
+                ip("10.1.2.3")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn check_renewed_token() {
+        let secret = hex::decode(KEY).unwrap();
+        let key = Key::Hmac(secret.clone());
+        let token = |issuer: &str, exp: u64, catr: bool| {
+            let mut builder = TokenBuilder::new()
+                .algorithm(Algorithm::HmacSha256)
+                .registered_claims(
+                    RegisteredClaims::new()
+                        .with_issuer(issuer)
+                        .with_subject("user_id:asset_id:session_id")
+                        .with_expiration(exp),
+                );
+            if catr {
+                builder = builder.custom_cbor(
+                    cat_keys::CATR,
+                    common_access_token::catr::create(common_access_token::catr::header_renewal(
+                        20,
+                        None,
+                        Some("CTA-Common-Access-Token"),
+                        None,
+                    )),
+                );
+            }
+            builder.sign(&secret).unwrap().to_bytes().unwrap()
+        };
+        let previous = cose::open(&token("eyevinn", 1040, true)).unwrap();
+        let violations = |renewed: &[u8]| check_renewal(&previous, renewed, &key, 1000).unwrap().1;
+
+        assert!(violations(&token("eyevinn", 1020, true)).is_empty());
+        assert!(violations(&token("eyevinn", 1023, true)).is_empty());
+        assert_eq!(
+            violations(&token("other", 1040, false)),
+            vec![
+                String::from("iss changed from Some(\"eyevinn\") to Some(\"other\")"),
+                String::from("catr is missing"),
+                String::from("exp 1040 is not the renewal time plus exp-add 20, expected 1020"),
+            ]
+        );
+        let forged = TokenBuilder::new()
+            .algorithm(Algorithm::HmacSha256)
+            .registered_claims(
+                RegisteredClaims::new()
+                    .with_issuer("eyevinn")
+                    .with_subject("user_id:asset_id:session_id")
+                    .with_expiration(1020),
+            )
+            .sign(&[1; 32])
+            .unwrap();
+        let violations = violations(&forged.to_bytes().unwrap());
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].starts_with("mac is not valid"));
+    }
+
+    #[test]
+    fn verify_token() {
+        let secret = hex::decode(KEY).unwrap();
+        let key = Key::Hmac(secret.clone());
+        let token = TokenBuilder::new()
+            .algorithm(Algorithm::HmacSha256)
+            .unprotected_key_id(KeyId::string("Symmetric256"))
+            .registered_claims(
+                RegisteredClaims::new()
+                    .with_expiration(1000)
+                    .with_not_before(100),
+            )
+            .custom_cbor(
+                cat_keys::CATU,
+                catu::create(BTreeMap::from([(
+                    uri_components::EXTENSION,
+                    catu::exact_match(".m3u8"),
+                )])),
+            )
+            .sign(&secret)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let request = RequestInfo {
+            url: Some(Url::parse("https://cdn.example.com/index.m3u8").unwrap()),
+            ..RequestInfo::default()
+        };
+
+        let verification = verify(&token, &key, &request, 500).unwrap();
+        assert!(verification.valid(), "{}", verification);
+
+        let verification = verify(&token, &Key::Hmac(vec![0; 32]), &request, 500).unwrap();
+        assert!(verification.mac.is_err());
+        assert!(!verification.valid());
+
+        let verification = verify(&token, &key, &RequestInfo::default(), 1000).unwrap();
+        let failed: Vec<&str> = verification.failed().map(|c| c.claim).collect();
+        assert_eq!(failed, vec!["exp"]);
+        assert!(
+            verification
+                .checks
+                .iter()
+                .any(|c| c.claim == "catu" && c.status == CheckStatus::Skipped)
+        );
+
+        let verification = verify(&token, &key, &RequestInfo::default(), 50).unwrap();
+        let failed: Vec<&str> = verification.failed().map(|c| c.claim).collect();
+        assert_eq!(failed, vec!["nbf"]);
+    }
+}